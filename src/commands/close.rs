@@ -19,7 +19,7 @@ pub fn close<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
             ctx.state.maximized_window_id = None;
             restore_sidebar_window_sizes(ctx)?;
         }
-        save_state(&ctx.state, &ctx.cache_dir)?;
+        save_state(&ctx.state, &ctx.runtime_dir)?;
     }
 
     let _ = ctx.socket.send_action(Action::CloseWindow {
@@ -58,7 +58,8 @@ mod tests_close {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         close(&mut ctx).expect("Close failed");
@@ -98,7 +99,8 @@ mod tests_close {
             state,
             config: Default::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         close(&mut ctx).expect("Close failed");
@@ -143,7 +145,8 @@ mod tests_close {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         close(&mut ctx).expect("Close failed");
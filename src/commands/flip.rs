@@ -1,12 +1,13 @@
 use crate::Ctx;
 use crate::commands::reorder;
 use crate::niri::NiriClient;
-use crate::state::save_state;
+use crate::state::{save_preferences, save_state};
 use anyhow::Result;
 
 pub fn toggle_flip<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
     ctx.state.is_flipped = !ctx.state.is_flipped;
-    save_state(&ctx.state, &ctx.cache_dir)?;
+    save_state(&ctx.state, &ctx.runtime_dir)?;
+    save_preferences(&ctx.state, &ctx.state_dir)?;
     reorder(ctx)?;
     Ok(())
 }
@@ -50,7 +51,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_flip(&mut ctx).expect("Toggle flip failed");
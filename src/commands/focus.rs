@@ -104,7 +104,8 @@ mod tests_focus {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         focus(&mut ctx, Direction::Next).unwrap();
@@ -158,7 +159,8 @@ mod tests_focus {
             state,
             config: Default::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         focus(&mut ctx, Direction::Prev).unwrap();
@@ -205,7 +207,8 @@ mod tests_focus {
             state,
             config: Default::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         // Next (Should focus first item -> A)
@@ -240,7 +243,8 @@ mod tests_focus {
             state: AppState::default(),
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         focus(&mut ctx, Direction::Next).unwrap();
@@ -6,7 +6,7 @@ use anyhow::Result;
 
 pub fn toggle_visibility<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
     ctx.state.is_hidden = !ctx.state.is_hidden;
-    save_state(&ctx.state, &ctx.cache_dir)?;
+    save_state(&ctx.state, &ctx.runtime_dir)?;
     reorder(ctx)?;
     Ok(())
 }
@@ -40,7 +40,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_visibility(&mut ctx).expect("Toggle visibility failed");
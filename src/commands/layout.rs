@@ -0,0 +1,144 @@
+use crate::commands::reorder::plan_reorder;
+use crate::layout::LayoutPlan;
+use crate::niri::NiriClient;
+use crate::{Ctx, PlanFormat};
+use anyhow::Result;
+use niri_ipc::{Action, PositionChange, SizeChange};
+use std::fmt::Write;
+
+pub fn layout<C: NiriClient>(ctx: &mut Ctx<C>, format: PlanFormat) -> Result<()> {
+    let plan = plan_reorder(ctx)?;
+    println!("{}", render_plan(&plan, format)?);
+    Ok(())
+}
+
+pub fn render_plan(plan: &LayoutPlan, format: PlanFormat) -> Result<String> {
+    match format {
+        PlanFormat::Table => Ok(render_table(plan)),
+        PlanFormat::Json => Ok(serde_json::to_string_pretty(plan)?),
+    }
+}
+
+fn render_table(plan: &LayoutPlan) -> String {
+    let mut out = String::new();
+    let screen = &plan.screen;
+    let _ = writeln!(
+        out,
+        "Workspace {} on {}x{} screen at scale {}, scroll offset {}",
+        plan.workspace_id, screen.width, screen.height, screen.scale, plan.scroll_offset
+    );
+
+    if plan.windows.is_empty() {
+        let _ = writeln!(out, "\nNo sidebar windows on this workspace");
+    } else {
+        let _ = writeln!(
+            out,
+            "\n{:>8} {:<20} {:>6} {:>6} {:>6} {:>6}  FOCUSED",
+            "ID", "APP ID", "X", "Y", "WIDTH", "HEIGHT"
+        );
+        for w in &plan.windows {
+            let _ = writeln!(
+                out,
+                "{:>8} {:<20} {:>6} {:>6} {:>6} {:>6}  {}",
+                w.id,
+                w.app_id.as_deref().unwrap_or("-"),
+                w.x,
+                w.y,
+                w.width,
+                w.height,
+                if w.is_focused { "yes" } else { "" }
+            );
+        }
+    }
+
+    if plan.actions.is_empty() {
+        let _ = write!(out, "\nNo actions needed");
+    } else {
+        let _ = writeln!(out, "\nActions:");
+        for action in &plan.actions {
+            let _ = writeln!(out, "  {}", describe_action(action));
+        }
+    }
+
+    out.trim_end().to_string()
+}
+
+fn describe_action(action: &Action) -> String {
+    match action {
+        Action::MoveFloatingWindow {
+            id: Some(id),
+            x: PositionChange::SetFixed(x),
+            y: PositionChange::SetFixed(y),
+        } => format!("move {} to ({}, {})", id, x, y),
+        Action::SetWindowWidth {
+            id: Some(id),
+            change: SizeChange::SetFixed(width),
+        } => format!("set width of {} to {}", id, width),
+        Action::SetWindowHeight {
+            id: Some(id),
+            change: SizeChange::SetFixed(height),
+        } => format!("set height of {} to {}", id, height),
+        other => format!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{PlannedWindow, Screen};
+
+    fn sample_plan() -> LayoutPlan {
+        LayoutPlan {
+            screen: Screen {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+                scale: 1.0,
+            },
+            workspace_id: 1,
+            scroll_offset: 0,
+            windows: vec![PlannedWindow {
+                id: 10,
+                app_id: Some("kitty".into()),
+                title: Some("shell".into()),
+                is_focused: true,
+                x: 1600.0,
+                y: 830.0,
+                width: 300,
+                height: 200,
+            }],
+            actions: vec![
+                Action::SetWindowHeight {
+                    change: SizeChange::SetFixed(200),
+                    id: Some(10),
+                },
+                Action::MoveFloatingWindow {
+                    id: Some(10),
+                    x: PositionChange::SetFixed(1600.0),
+                    y: PositionChange::SetFixed(830.0),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_render_table_lists_windows_and_actions() {
+        let table = render_plan(&sample_plan(), PlanFormat::Table).unwrap();
+
+        assert!(table.starts_with("Workspace 1 on 1920x1080 screen at scale 1, scroll offset 0"));
+        assert!(table.contains("      10 kitty                  1600    830    300    200  yes"));
+        assert!(table.contains("  set height of 10 to 200"));
+        assert!(table.contains("  move 10 to (1600, 830)"));
+    }
+
+    #[test]
+    fn test_render_json_is_parseable() {
+        let json = render_plan(&sample_plan(), PlanFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value["windows"][0]["id"], 10);
+        assert_eq!(value["windows"][0]["x"], 1600.0);
+        assert_eq!(value["actions"].as_array().unwrap().len(), 2);
+    }
+}
@@ -1,100 +1,459 @@
 use crate::commands::maximize::restore_sidebar_window_sizes;
-use crate::commands::movefrom::move_to;
+use crate::commands::movefrom::move_windows;
+use crate::commands::preset::apply_preset;
 use crate::commands::reorder;
 use crate::commands::togglewindow::add_to_sidebar;
-use crate::config::load_config;
-use crate::niri::connect;
-use crate::state::{get_default_cache_dir, load_state, save_state};
-use crate::window_rules::resolve_auto_add;
-use crate::{Ctx, NiriClient};
+use crate::config::{Config, load_config};
+use crate::hooks::{diff_state, run_hooks};
+use crate::niri::{EventStreamClient, connect};
+use crate::recording::{Entry, Recorder, RecordingClient};
+use crate::state::{load_session_state, save_state, state_path};
+use crate::window_rules::{matching_rule_index, resolve_auto_add, resolve_max_instances};
+use crate::{AppState, Ctx, NiriClient, debug, error, info, trace, warn};
 use anyhow::Result;
 use fslock::LockFile;
 use niri_ipc::socket::Socket;
-use niri_ipc::{Event, Request, Window};
-
-pub fn listen(mut ctx: Ctx<Socket>) -> Result<()> {
-    let _ = ctx.socket.send(Request::EventStream)?;
-    let mut read_event = ctx.socket.read_events();
-    println!("niri-sidebar: Listening for window events...");
+use niri_ipc::{Event, LogicalOutput, Mode, Output, Request, Window};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant, SystemTime};
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+// Events arriving within this long of each other are handled as one burst
+const COALESCE_WINDOW: Duration = Duration::from_millis(25);
+// Upper bound for a burst, so a steady flood of events can't stall the sidebar forever
+const MAX_BURST_DURATION: Duration = Duration::from_millis(250);
+
+/// Runs the daemon, writing everything it sees and does to `record` if given.
+pub fn listen(ctx: Ctx<Socket>, config_path: PathBuf, record: Option<PathBuf>) -> Result<()> {
+    let Ctx {
+        config,
+        socket,
+        runtime_dir,
+        state_dir,
+        ..
+    } = ctx;
+    let recorder = record.as_deref().map(Recorder::create).transpose()?;
+    let mut listener = Listener::new(
+        Ctx {
+            state: AppState::default(),
+            config,
+            socket: EventStreamClient::new(RecordingClient::new(socket, recorder.clone())),
+            runtime_dir,
+            state_dir,
+        },
+        config_path,
+        recorder,
+    )?;
+    let mut is_reconnect = false;
+    let mut reconnect_delay = MIN_RECONNECT_DELAY;
 
     loop {
-        let event = match read_event() {
-            Ok(e) => e,
+        let events = match subscribe(&mut listener, is_reconnect) {
+            Ok(events) => events,
             Err(e) => {
-                eprintln!("niri-sidebar: IPC error: {}", e);
-                anyhow::bail!("Fatal IPC error: {}", e);
+                error!(
+                    "Failed to connect to niri: {:#}. Retrying in {:?}",
+                    e, reconnect_delay
+                );
+                sleep(reconnect_delay);
+                reconnect_delay = next_reconnect_delay(reconnect_delay);
+                continue;
             }
         };
 
-        match event {
-            Event::WindowClosed { id } => handle_close_event(id)?,
-            Event::WindowFocusChanged { .. } => handle_focus_change()?,
-            Event::WorkspaceActivated { id, focused: true } => handle_workspace_focus(id)?,
-            Event::WindowOpenedOrChanged { window } => handle_new_window(&window)?,
-            _ => {}
+        if is_reconnect {
+            info!("Reconnected to niri, resyncing sidebar...");
+            if let Err(e) = listener.resync() {
+                error!("Failed to resync sidebar: {:#}", e);
+            }
+        } else {
+            info!("Listening for window events...");
+        }
+
+        loop {
+            let first = match events.recv() {
+                Ok(Ok(e)) => e,
+                Ok(Err(e)) => {
+                    warn!("IPC error: {}. Reconnecting...", e);
+                    break;
+                }
+                Err(_) => break,
+            };
+            reconnect_delay = MIN_RECONNECT_DELAY;
+
+            let (burst, error) = collect_burst(&events, first);
+            if let Err(e) = listener.handle_events(burst) {
+                error!("Failed to handle event: {:#}", e);
+            }
+            if let Some(e) = error {
+                warn!("IPC error: {}. Reconnecting...", e);
+                break;
+            }
+        }
+
+        is_reconnect = true;
+        sleep(reconnect_delay);
+        reconnect_delay = next_reconnect_delay(reconnect_delay);
+    }
+}
+
+/// Opens the event stream, and after a dropped connection also a fresh request connection.
+///
+/// Events are read on a separate thread so the main loop can wait for them with a timeout.
+fn subscribe(listener: &mut Listener, is_reconnect: bool) -> Result<Receiver<io::Result<Event>>> {
+    let mut events = connect()?;
+    match events.send(Request::EventStream)? {
+        Ok(_) => {}
+        Err(e) => anyhow::bail!("niri refused the event stream: {}", e),
+    }
+    if is_reconnect {
+        let recorder = listener.recorder.clone();
+        listener.ctx.socket = EventStreamClient::new(RecordingClient::new(connect()?, recorder));
+    }
+
+    let mut read_event = events.read_events();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        loop {
+            let event = read_event();
+            let failed = event.is_err();
+            if sender.send(event).is_err() || failed {
+                break;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+/// Gathers every event that follows `first` without a pause longer than [`COALESCE_WINDOW`].
+fn collect_burst(
+    events: &Receiver<io::Result<Event>>,
+    first: Event,
+) -> (Vec<Event>, Option<io::Error>) {
+    let deadline = Instant::now() + MAX_BURST_DURATION;
+    let mut burst = vec![first];
+
+    loop {
+        let timeout = COALESCE_WINDOW.min(deadline.saturating_duration_since(Instant::now()));
+        match events.recv_timeout(timeout) {
+            Ok(Ok(event)) => burst.push(event),
+            Ok(Err(e)) => return (burst, Some(e)),
+            Err(RecvTimeoutError::Timeout) => return (burst, None),
+            Err(RecvTimeoutError::Disconnected) => {
+                return (burst, Some(io::Error::other("event stream closed")));
+            }
+        }
+    }
+}
+
+fn next_reconnect_delay(delay: Duration) -> Duration {
+    (delay * 2).min(MAX_RECONNECT_DELAY)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+struct Listener {
+    ctx: Ctx<EventStreamClient<RecordingClient<Socket>>>,
+    // The config file as written, `ctx.config` is this with the active profile and preset applied
+    base_config: Config,
+    profile_config: Config,
+    profile: Option<String>,
+    outputs: OutputWatch,
+    // State as of the last event, to find out which hooks to run
+    last_state: AppState,
+    state_modified: Option<SystemTime>,
+    config_path: PathBuf,
+    config_modified: Option<SystemTime>,
+    recorder: Option<Recorder>,
+}
+
+impl Listener {
+    fn new(
+        mut ctx: Ctx<EventStreamClient<RecordingClient<Socket>>>,
+        config_path: PathBuf,
+        recorder: Option<Recorder>,
+    ) -> Result<Self> {
+        let _lock = lock(&ctx.runtime_dir)?;
+        ctx.state = load_session_state(&ctx.runtime_dir, &ctx.state_dir)?;
+        let mut listener = Self {
+            last_state: ctx.state.clone(),
+            state_modified: modified_time(&state_path(&ctx.runtime_dir)),
+            config_modified: modified_time(&config_path),
+            config_path,
+            base_config: ctx.config.clone(),
+            profile_config: ctx.config.clone(),
+            profile: None,
+            outputs: OutputWatch::default(),
+            ctx,
+            recorder,
+        };
+        listener.outputs.check(&mut listener.ctx.socket)?;
+        listener.apply_profile()?;
+        Ok(listener)
+    }
+
+    /// Picks the profile for the outputs seen last, returns whether it changed.
+    fn apply_profile(&mut self) -> Result<bool> {
+        let (config, profile) = self.base_config.for_outputs(self.outputs.outputs())?;
+        self.profile_config = config;
+        self.ctx.config = apply_preset(&self.profile_config, &self.ctx.state);
+
+        if profile == self.profile {
+            return Ok(false);
+        }
+        match &profile {
+            Some(name) => info!("Switched to profile {}", name),
+            None => info!("No profile matches the outputs, using the base config"),
+        }
+        self.profile = profile;
+        Ok(true)
+    }
+
+    /// Picks up changes CLI commands made to the state file, and edits to the config file.
+    fn reload_if_changed(&mut self) -> Result<()> {
+        let state_modified = modified_time(&state_path(&self.ctx.runtime_dir));
+        if state_modified != self.state_modified {
+            let preset = self.ctx.state.active_preset.take();
+            self.ctx.state = load_session_state(&self.ctx.runtime_dir, &self.ctx.state_dir)?;
+            self.state_modified = state_modified;
+            if self.ctx.state.active_preset != preset {
+                self.ctx.config = apply_preset(&self.profile_config, &self.ctx.state);
+            }
+        }
+
+        let config_modified = modified_time(&self.config_path);
+        if config_modified != self.config_modified {
+            self.base_config = load_config(&self.config_path);
+            self.config_modified = config_modified;
+            self.apply_profile()?;
+        }
+        Ok(())
+    }
+
+    fn resync(&mut self) -> Result<()> {
+        let _lock = lock(&self.ctx.runtime_dir)?;
+        self.reload_if_changed()?;
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&Entry::Resync {
+                state: self.ctx.state.clone(),
+                config: self.ctx.config.clone(),
+            });
+        }
+        let result = reorder(&mut self.ctx);
+        self.finish(result, HashMap::new())
+    }
+
+    fn handle_events(&mut self, events: Vec<Event>) -> Result<()> {
+        let _lock = lock(&self.ctx.runtime_dir)?;
+        self.reload_if_changed()?;
+
+        // Keep closed windows around until the hooks had a chance to report on them
+        let mut closed = HashMap::new();
+        let mut burst = EventBurst::default();
+        let recorded = self.recorder.as_ref().map(|_| events.clone());
+        for event in events {
+            trace!("Event {:?}", event);
+            if let Event::WindowClosed { id } = &event
+                && let Some(window) = self.ctx.socket.window(*id)
+            {
+                closed.insert(*id, window.clone());
+            }
+            burst.push(&event);
+            self.ctx.socket.apply(event);
+        }
+
+        // niri sends no event when an output changes its mode or scale, so every burst checks
+        match self.outputs.check(&mut self.ctx.socket) {
+            Ok(changed) => burst.outputs_changed = changed,
+            Err(e) => error!("Failed to query the outputs: {:#}", e),
+        }
+        if burst.outputs_changed
+            && let Err(e) = self.apply_profile()
+        {
+            error!("Failed to pick a profile for the outputs: {:#}", e);
+        }
+        if let (Some(recorder), Some(events)) = (&self.recorder, recorded) {
+            recorder.record(&Entry::Burst {
+                events,
+                state: self.ctx.state.clone(),
+                config: self.ctx.config.clone(),
+                outputs_changed: burst.outputs_changed,
+            });
         }
+        // After recording the burst, so a replay sees the query this may send as part of it
+        self.ctx.socket.mark_synced();
+        let result = process_burst(&mut self.ctx, &burst);
+        self.finish(result, closed)
+    }
+
+    fn finish(&mut self, result: Result<()>, mut windows: HashMap<u64, Window>) -> Result<()> {
+        if result.is_err() {
+            // A failed handler may have left changes in memory it never saved
+            self.ctx.state = load_session_state(&self.ctx.runtime_dir, &self.ctx.state_dir)?;
+        }
+        self.state_modified = modified_time(&state_path(&self.ctx.runtime_dir));
+
+        // Hooks still fire for whatever the handler managed to change before failing
+        let hook_events = diff_state(&self.last_state, &self.ctx.state);
+        if !hook_events.is_empty() {
+            windows.extend(
+                self.ctx
+                    .socket
+                    .windows()
+                    .iter()
+                    .map(|(id, w)| (*id, w.clone())),
+            );
+            run_hooks(&self.ctx.config.hooks, &hook_events, &windows);
+        }
+        self.last_state = self.ctx.state.clone();
+
+        result
     }
 }
 
-fn get_ctx() -> Result<(Ctx<Socket>, LockFile)> {
-    let cache_dir = get_default_cache_dir()?;
-    let mut lock_path = cache_dir.clone();
+fn lock(runtime_dir: &Path) -> Result<LockFile> {
+    let mut lock_path = runtime_dir.to_path_buf();
     lock_path.push("instance.lock");
     let mut lock_file = LockFile::open(&lock_path)?;
     lock_file.lock()?;
+    Ok(lock_file)
+}
 
-    let state = load_state(&cache_dir)?;
-    let config = load_config();
-    let ctx = Ctx {
-        state,
-        config,
-        socket: connect()?,
-        cache_dir,
-    };
+/// The outputs as of the last check, to tell hotplugs and mode changes from workspace churn.
+#[derive(Debug, Default)]
+pub struct OutputWatch {
+    outputs: Vec<Output>,
+}
 
-    Ok((ctx, lock_file))
+impl OutputWatch {
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    /// Queries the outputs again, returns whether one was plugged in or out or changed its mode,
+    /// logical size or scale since the last check.
+    pub fn check<C: NiriClient>(&mut self, client: &mut C) -> Result<bool> {
+        let outputs = client.get_outputs()?;
+        let changed = layout_of(&outputs) != layout_of(&self.outputs);
+        self.outputs = outputs;
+        Ok(changed)
+    }
 }
 
-fn handle_close_event(closed_id: u64) -> Result<()> {
-    let (mut ctx, _lock) = get_ctx()?;
-    process_close(&mut ctx, closed_id)
+/// The parts of the outputs the layout depends on, Output itself can't be compared.
+fn layout_of(outputs: &[Output]) -> Vec<(&str, Option<Mode>, Option<LogicalOutput>)> {
+    let mut layout: Vec<_> = outputs
+        .iter()
+        .map(|o| {
+            let mode = o.current_mode.and_then(|i| o.modes.get(i)).copied();
+            (o.name.as_str(), mode, o.logical)
+        })
+        .collect();
+    layout.sort_by(|a, b| a.0.cmp(b.0));
+    layout
 }
 
-fn handle_focus_change() -> Result<()> {
-    let (mut ctx, _lock) = get_ctx()?;
-    process_focus(&mut ctx)
+/// The changes from a burst of events that arrived close together, with repeats collapsed.
+#[derive(Debug, Default)]
+pub struct EventBurst {
+    pub closed: Vec<u64>,
+    pub changed: Vec<Window>,
+    pub focused_workspace: Option<u64>,
+    pub focus_changed: bool,
+    /// Set from an [`OutputWatch`] check, the events alone don't tell.
+    pub outputs_changed: bool,
 }
 
-fn handle_workspace_focus(ws_id: u64) -> Result<()> {
-    let (mut ctx, _lock) = get_ctx()?;
-    if ctx.config.interaction.sticky {
-        process_move(&mut ctx, ws_id)
-    } else {
-        Ok(())
+impl EventBurst {
+    pub fn push(&mut self, event: &Event) {
+        match event {
+            Event::WindowClosed { id } => {
+                self.changed.retain(|w| w.id != *id);
+                self.closed.push(*id);
+            }
+            Event::WindowFocusChanged { .. } => self.focus_changed = true,
+            Event::WorkspaceActivated { id, focused: true } => self.focused_workspace = Some(*id),
+            // Only the latest version of a window matters, e.g. for apps flooding title changes
+            Event::WindowOpenedOrChanged { window } => {
+                match self.changed.iter_mut().find(|w| w.id == window.id) {
+                    Some(existing) => *existing = window.clone(),
+                    None => self.changed.push(window.clone()),
+                }
+            }
+            _ => {}
+        }
     }
 }
 
-fn handle_new_window(window: &Window) -> Result<()> {
-    let (mut ctx, _lock) = get_ctx()?;
-    process_new_window(&mut ctx, window)
+/// Handles a whole burst of events with at most one reorder (or one focus settle) at the end.
+pub fn process_burst<C: NiriClient>(ctx: &mut Ctx<C>, burst: &EventBurst) -> Result<()> {
+    let mut first_error = None;
+    let mut needs_reorder = false;
+    let mut check = |result: Result<bool>| match result {
+        Ok(changed) => needs_reorder |= changed,
+        // The first error is returned, anything after it would otherwise be lost
+        Err(e) if first_error.is_some() => {
+            error!("Failed to handle event: {:#}", e)
+        }
+        Err(e) => first_error = Some(e),
+    };
+
+    for id in &burst.closed {
+        check(forget_closed_window(ctx, *id));
+    }
+    if ctx.config.interaction.sticky
+        && let Some(ws_id) = burst.focused_workspace
+    {
+        check(follow_workspace(ctx, ws_id));
+    }
+    for window in &burst.changed {
+        check(adopt_new_window(ctx, window));
+    }
+
+    let result = if burst.focus_changed {
+        process_focus(ctx)
+    } else if needs_reorder || burst.outputs_changed {
+        reorder(ctx)
+    } else {
+        Ok(())
+    };
+
+    match first_error {
+        Some(e) => Err(e),
+        None => result,
+    }
 }
 
 pub fn process_close<C: NiriClient>(ctx: &mut Ctx<C>, closed_id: u64) -> Result<()> {
-    if let Some(index) = ctx.state.windows.iter().position(|w| w.id == closed_id) {
-        println!("Sidebar window {} closed. Reordering...", closed_id);
+    if forget_closed_window(ctx, closed_id)? {
+        reorder(ctx)?;
+    }
+    Ok(())
+}
 
-        ctx.state.windows.remove(index);
-        if ctx.state.maximized_window_id == Some(closed_id) {
-            ctx.state.maximized_window_id = None;
-            restore_sidebar_window_sizes(ctx)?;
-        }
-        save_state(&ctx.state, &ctx.cache_dir)?;
-        dbg!(&ctx.state);
+/// Drops a closed window from the sidebar, returns whether the sidebar needs a reorder.
+fn forget_closed_window<C: NiriClient>(ctx: &mut Ctx<C>, closed_id: u64) -> Result<bool> {
+    let Some(index) = ctx.state.windows.iter().position(|w| w.id == closed_id) else {
+        return Ok(false);
+    };
+    debug!("Sidebar window {} closed, reordering", closed_id);
 
-        reorder(ctx)?;
+    ctx.state.windows.remove(index);
+    if ctx.state.maximized_window_id == Some(closed_id) {
+        ctx.state.maximized_window_id = None;
+        restore_sidebar_window_sizes(ctx)?;
     }
+    save_state(&ctx.state, &ctx.runtime_dir)?;
 
-    Ok(())
+    Ok(true)
 }
 
 pub fn process_focus<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
@@ -111,7 +470,7 @@ pub fn process_focus<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
         && ctx.state.maximized_window_id != Some(focused_id)
     {
         ctx.state.maximized_window_id = Some(focused_id);
-        save_state(&ctx.state, &ctx.cache_dir)?;
+        save_state(&ctx.state, &ctx.runtime_dir)?;
     }
 
     reorder(ctx)?;
@@ -126,17 +485,47 @@ pub fn process_focus<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
     Ok(())
 }
 
-pub fn process_move<C: NiriClient>(ctx: &mut Ctx<C>, ws_id: u64) -> Result<()> {
+/// Sticky mode: brings the sidebar along to a newly focused workspace, returns whether it moved.
+///
+/// The workspace may be on another output, so a move always needs a reorder to lay the windows
+/// out against the new output.
+fn follow_workspace<C: NiriClient>(ctx: &mut Ctx<C>, ws_id: u64) -> Result<bool> {
+    if let Some(output) = &ctx.config.interaction.sticky_output {
+        let on_output = ctx
+            .socket
+            .get_workspaces()?
+            .iter()
+            .any(|w| w.id == ws_id && w.output.as_ref() == Some(output));
+        if !on_output {
+            return Ok(false);
+        }
+    }
+    process_move(ctx, ws_id)
+}
+
+/// Moves all sidebar windows to a workspace, returns whether any of them wasn't there yet.
+pub fn process_move<C: NiriClient>(ctx: &mut Ctx<C>, ws_id: u64) -> Result<bool> {
     let windows: Vec<_> = ctx.socket.get_windows()?;
-    let sidebar_windows = windows
+    let sidebar_windows: Vec<_> = windows
         .iter()
-        .filter(|w| ctx.state.windows.iter().any(|ws| ws.id == w.id))
+        .filter(|w| {
+            w.workspace_id != Some(ws_id) && ctx.state.windows.iter().any(|ws| ws.id == w.id)
+        })
         .collect();
-    move_to(ctx, sidebar_windows, ws_id)?;
-    Ok(())
+    let moved = !sidebar_windows.is_empty();
+    move_windows(ctx, sidebar_windows, ws_id)?;
+    Ok(moved)
 }
 
 pub fn process_new_window<C: NiriClient>(ctx: &mut Ctx<C>, window: &Window) -> Result<()> {
+    if adopt_new_window(ctx, window)? {
+        reorder(ctx)?;
+    }
+    Ok(())
+}
+
+/// Auto-adds a new or changed window if a rule asks for it, returns whether it was added.
+fn adopt_new_window<C: NiriClient>(ctx: &mut Ctx<C>, window: &Window) -> Result<bool> {
     // If window is removed from sidebar a WindowOpenedOrChanged event will happen
     // and this if let will catch that and remove id from vector, prevents auto_add
     // from being triggered immediately after window is removed from sidebar
@@ -147,18 +536,41 @@ pub fn process_new_window<C: NiriClient>(ctx: &mut Ctx<C>, window: &Window) -> R
         .position(|id| id == &window.id)
     {
         ctx.state.ignored_windows.remove(index);
-        return Ok(());
+        return Ok(false);
     }
 
     if resolve_auto_add(&ctx.config.window_rule, window)
         && !ctx.state.windows.iter().any(|w| w.id == window.id)
+        && !reached_max_instances(ctx, window)?
     {
         add_to_sidebar(ctx, window)?;
-        save_state(&ctx.state, &ctx.cache_dir)?;
-        reorder(ctx)?;
+        save_state(&ctx.state, &ctx.runtime_dir)?;
+        return Ok(true);
     }
 
-    Ok(())
+    Ok(false)
+}
+
+fn reached_max_instances<C: NiriClient>(ctx: &mut Ctx<C>, window: &Window) -> Result<bool> {
+    let rules = &ctx.config.window_rule;
+    let Some(max_instances) = resolve_max_instances(rules, window) else {
+        return Ok(false);
+    };
+
+    // Only windows that resolve to the same rule count towards its limit
+    let rule_index = matching_rule_index(rules, window);
+    let instances = ctx
+        .socket
+        .get_windows()?
+        .iter()
+        .filter(|w| {
+            w.id != window.id
+                && ctx.state.windows.iter().any(|ws| ws.id == w.id)
+                && matching_rule_index(rules, w) == rule_index
+        })
+        .count();
+
+    Ok(instances >= max_instances)
 }
 
 #[cfg(test)]
@@ -166,11 +578,23 @@ mod tests {
     use super::*;
     use crate::config::{Config, WindowRule};
     use crate::state::{AppState, WindowState};
-    use crate::test_utils::{MockNiri, mock_window};
+    use crate::test_utils::{MockNiri, mock_config, mock_window, mock_workspace};
     use niri_ipc::{Action, WorkspaceReferenceArg};
     use regex::Regex;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_reconnect_delay_doubles_up_to_max() {
+        assert_eq!(
+            next_reconnect_delay(MIN_RECONNECT_DELAY),
+            MIN_RECONNECT_DELAY * 2
+        );
+        assert_eq!(
+            next_reconnect_delay(MAX_RECONNECT_DELAY),
+            MAX_RECONNECT_DELAY
+        );
+    }
+
     #[test]
     fn test_process_close_removes_window_and_reorders() {
         let temp_dir = tempdir().unwrap();
@@ -204,7 +628,8 @@ mod tests {
             state,
             config: Config::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         process_close(&mut ctx, 100).expect("Process close failed");
@@ -240,7 +665,8 @@ mod tests {
             state,
             config: Config::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         process_close(&mut ctx, 999).expect("Process close failed");
@@ -291,7 +717,8 @@ mod tests {
             state,
             config: Config::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         let target_ws = 99;
@@ -355,7 +782,8 @@ mod tests {
             state,
             config: Config::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         process_focus(&mut ctx).expect("process_focus failed");
@@ -387,7 +815,8 @@ mod tests {
             state,
             config: Config::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         process_focus(&mut ctx).expect("process_focus failed");
@@ -425,7 +854,8 @@ mod tests {
             state,
             config: Config::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         process_focus(&mut ctx).expect("process_focus failed");
@@ -470,7 +900,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         let w100 = mock_window(100, true, true, 1, Some((1.0, 2.0)));
@@ -509,7 +940,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         let w100 = mock_window(100, true, true, 1, Some((1.0, 2.0)));
@@ -538,7 +970,8 @@ mod tests {
             state,
             config: Config::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         let w100 = mock_window(100, true, true, 1, Some((1.0, 2.0)));
@@ -577,7 +1010,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         let w100 = mock_window(100, true, true, 1, Some((1.0, 2.0)));
@@ -589,4 +1023,324 @@ mod tests {
         // Reorder should not have run
         assert!(ctx.socket.sent_actions.is_empty());
     }
+
+    #[test]
+    fn test_process_new_window_respects_max_instances() {
+        let temp_dir = tempdir().unwrap();
+
+        let mut state = AppState::default();
+        state.windows.push(WindowState {
+            id: 100,
+            width: 500,
+            height: 500,
+            is_floating: false,
+            position: None,
+        });
+
+        let w100 = mock_window(100, false, true, 1, Some((1.0, 2.0)));
+        let w200 = mock_window(200, true, false, 1, None);
+        let mock = MockNiri::new(vec![w100, w200.clone()]);
+
+        let config = Config {
+            window_rule: vec![WindowRule {
+                app_id: Some(Regex::new(r"test").unwrap()),
+                auto_add: true,
+                max_instances: Some(1),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let mut ctx = Ctx {
+            state,
+            config,
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        process_new_window(&mut ctx, &w200).expect("Process new window failed");
+
+        // 200 left tiled since 100 already fills the rule's only slot
+        assert_eq!(ctx.state.windows.len(), 1);
+        assert_eq!(ctx.state.windows[0].id, 100);
+        assert!(ctx.socket.sent_actions.is_empty());
+    }
+
+    #[test]
+    fn test_process_new_window_adds_below_max_instances() {
+        let temp_dir = tempdir().unwrap();
+
+        let mut state = AppState::default();
+        state.windows.push(WindowState {
+            id: 100,
+            width: 500,
+            height: 500,
+            is_floating: false,
+            position: None,
+        });
+
+        let w100 = mock_window(100, false, true, 1, Some((1.0, 2.0)));
+        let w200 = mock_window(200, true, false, 1, None);
+        let mock = MockNiri::new(vec![w100, w200.clone()]);
+
+        let config = Config {
+            window_rule: vec![WindowRule {
+                app_id: Some(Regex::new(r"test").unwrap()),
+                auto_add: true,
+                max_instances: Some(2),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let mut ctx = Ctx {
+            state,
+            config,
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        process_new_window(&mut ctx, &w200).expect("Process new window failed");
+
+        assert_eq!(ctx.state.windows.len(), 2);
+        assert_eq!(ctx.state.windows[1].id, 200);
+    }
+
+    #[test]
+    fn test_event_burst_collapses_repeated_window_changes() {
+        let mut burst = EventBurst::default();
+        for title in ["a", "b", "c"] {
+            let mut window = mock_window(1, false, false, 1, None);
+            window.title = Some(title.into());
+            burst.push(&Event::WindowOpenedOrChanged { window });
+        }
+        burst.push(&Event::WindowOpenedOrChanged {
+            window: mock_window(2, false, false, 1, None),
+        });
+        burst.push(&Event::WindowClosed { id: 2 });
+        burst.push(&Event::WindowFocusChanged { id: Some(1) });
+
+        assert_eq!(burst.changed.len(), 1);
+        assert_eq!(burst.changed[0].title.as_deref(), Some("c"));
+        assert_eq!(burst.closed, vec![2]);
+        assert!(burst.focus_changed);
+    }
+
+    #[test]
+    fn test_process_burst_reorders_once() {
+        let temp_dir = tempdir().unwrap();
+
+        let mut state = AppState::default();
+        for id in [10, 20] {
+            state.windows.push(WindowState {
+                id,
+                width: 100,
+                height: 100,
+                is_floating: true,
+                position: Some((1.0, 2.0)),
+            });
+        }
+
+        let w10 = mock_window(10, true, true, 1, Some((1.0, 2.0)));
+        let w20 = mock_window(20, false, true, 1, Some((1.0, 2.0)));
+        let mock = MockNiri::new(vec![w10.clone(), w20]);
+
+        let mut ctx = Ctx {
+            state,
+            config: Config::default(),
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        let mut burst = EventBurst::default();
+        for _ in 0..5 {
+            burst.push(&Event::WindowOpenedOrChanged {
+                window: w10.clone(),
+            });
+            burst.push(&Event::WindowFocusChanged { id: Some(10) });
+        }
+        burst.push(&Event::WindowClosed { id: 30 });
+
+        process_burst(&mut ctx, &burst).expect("process_burst failed");
+
+        let move_count = ctx
+            .socket
+            .sent_actions
+            .iter()
+            .filter(|a| matches!(a, Action::MoveFloatingWindow { id: Some(10), .. }))
+            .count();
+        assert_eq!(move_count, 1);
+    }
+
+    fn sticky_ctx(temp_dir: &Path, sticky_output: Option<&str>) -> Ctx<MockNiri> {
+        let mut state = AppState::default();
+        for id in [10, 20] {
+            state.windows.push(WindowState {
+                id,
+                width: 300,
+                height: 200,
+                is_floating: true,
+                position: None,
+            });
+        }
+
+        // 10 is still on the laptop panel, 20 already made it to the external monitor
+        let mut mock = MockNiri::new(vec![
+            mock_window(10, false, true, 1, Some((0.0, 0.0))),
+            mock_window(20, false, true, 2, Some((0.0, 0.0))),
+        ]);
+        mock.workspaces[0].is_focused = false;
+        mock.workspaces.push(mock_workspace(2, 1, "HDMI-A-1", true));
+
+        let mut config = mock_config();
+        config.interaction.sticky = true;
+        config.interaction.sticky_output = sticky_output.map(String::from);
+
+        Ctx {
+            state,
+            config,
+            socket: mock,
+            runtime_dir: temp_dir.to_path_buf(),
+            state_dir: temp_dir.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn test_sticky_follows_to_other_output_and_reorders() {
+        let temp_dir = tempdir().unwrap();
+        let mut ctx = sticky_ctx(temp_dir.path(), None);
+
+        let mut burst = EventBurst::default();
+        burst.push(&Event::WorkspaceActivated {
+            id: 2,
+            focused: true,
+        });
+        process_burst(&mut ctx, &burst).expect("process_burst failed");
+
+        let actions = &ctx.socket.sent_actions;
+        assert!(actions.iter().any(|a| matches!(
+            a,
+            Action::MoveWindowToWorkspace {
+                window_id: Some(10),
+                reference: WorkspaceReferenceArg::Id(2),
+                ..
+            }
+        )));
+        assert!(!actions.iter().any(|a| matches!(
+            a,
+            Action::MoveWindowToWorkspace {
+                window_id: Some(20),
+                ..
+            }
+        )));
+        // Laid out again even though no window focus changed
+        assert!(
+            actions
+                .iter()
+                .any(|a| matches!(a, Action::MoveFloatingWindow { id: Some(20), .. }))
+        );
+    }
+
+    #[test]
+    fn test_sticky_output_ignores_workspaces_on_other_outputs() {
+        let temp_dir = tempdir().unwrap();
+        let mut ctx = sticky_ctx(temp_dir.path(), Some("eDP-1"));
+
+        let mut burst = EventBurst::default();
+        burst.push(&Event::WorkspaceActivated {
+            id: 2,
+            focused: true,
+        });
+        process_burst(&mut ctx, &burst).expect("process_burst failed");
+
+        assert!(ctx.socket.sent_actions.is_empty());
+    }
+
+    fn hotplug_ctx(temp_dir: &Path) -> (Ctx<MockNiri>, OutputWatch) {
+        let mut state = AppState::default();
+        state.windows.push(WindowState {
+            id: 10,
+            width: 300,
+            height: 200,
+            is_floating: true,
+            position: None,
+        });
+        let mut ctx = Ctx {
+            state,
+            config: mock_config(),
+            socket: MockNiri::new(vec![mock_window(10, false, true, 1, Some((0.0, 0.0)))]),
+            runtime_dir: temp_dir.to_path_buf(),
+            state_dir: temp_dir.to_path_buf(),
+        };
+        let mut outputs = OutputWatch::default();
+        assert!(outputs.check(&mut ctx.socket).unwrap());
+        (ctx, outputs)
+    }
+
+    #[test]
+    fn test_process_burst_relayouts_when_outputs_change() {
+        let temp_dir = tempdir().unwrap();
+        let (mut ctx, mut outputs) = hotplug_ctx(temp_dir.path());
+        // Docked to a monitor taking over the same output name
+        if let Some(logical) = &mut ctx.socket.outputs[0].logical {
+            logical.width = 2560;
+            logical.scale = 1.25;
+        }
+
+        let mut burst = EventBurst::default();
+        burst.push(&Event::WorkspacesChanged { workspaces: vec![] });
+        burst.outputs_changed = outputs.check(&mut ctx.socket).unwrap();
+        assert!(burst.outputs_changed);
+        process_burst(&mut ctx, &burst).expect("process_burst failed");
+
+        assert!(
+            ctx.socket
+                .sent_actions
+                .iter()
+                .any(|a| matches!(a, Action::MoveFloatingWindow { id: Some(10), .. }))
+        );
+    }
+
+    #[test]
+    fn test_process_burst_ignores_workspace_churn() {
+        let temp_dir = tempdir().unwrap();
+        let (mut ctx, mut outputs) = hotplug_ctx(temp_dir.path());
+
+        // A dynamic workspace appearing, the outputs stay the same
+        let mut burst = EventBurst::default();
+        burst.push(&Event::WorkspacesChanged {
+            workspaces: vec![mock_workspace(1, 1, "eDP-1", true)],
+        });
+        burst.outputs_changed = outputs.check(&mut ctx.socket).unwrap();
+        assert!(!burst.outputs_changed);
+        process_burst(&mut ctx, &burst).expect("process_burst failed");
+
+        assert!(ctx.socket.sent_actions.is_empty());
+    }
+
+    #[test]
+    fn test_process_burst_relayouts_when_only_the_scale_changes() {
+        let temp_dir = tempdir().unwrap();
+        let (mut ctx, mut outputs) = hotplug_ctx(temp_dir.path());
+        // `niri msg output eDP-1 scale 2` sends no workspace event, only new window layouts
+        if let Some(logical) = &mut ctx.socket.outputs[0].logical {
+            logical.scale = 2.0;
+        }
+
+        let mut burst = EventBurst::default();
+        burst.push(&Event::WindowLayoutsChanged { changes: vec![] });
+        burst.outputs_changed = outputs.check(&mut ctx.socket).unwrap();
+        assert!(burst.outputs_changed);
+        process_burst(&mut ctx, &burst).expect("process_burst failed");
+
+        assert!(
+            ctx.socket
+                .sent_actions
+                .iter()
+                .any(|a| matches!(a, Action::MoveFloatingWindow { id: Some(10), .. }))
+        );
+    }
 }
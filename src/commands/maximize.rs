@@ -21,7 +21,7 @@ pub fn maximize<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
         restore_sidebar_window_sizes(ctx)?;
     }
 
-    save_state(&ctx.state, &ctx.cache_dir)?;
+    save_state(&ctx.state, &ctx.runtime_dir)?;
     reorder(ctx)?;
     Ok(())
 }
@@ -29,6 +29,7 @@ pub fn maximize<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
 pub(crate) fn restore_sidebar_window_sizes<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
     let windows = ctx.socket.get_windows()?;
     let current_ws = ctx.socket.get_active_workspace()?.id;
+    let screen = ctx.socket.get_screen()?;
 
     for window in windows.iter().filter(|w| {
         w.workspace_id == Some(current_ws) && ctx.state.windows.iter().any(|ws| ws.id == w.id)
@@ -38,6 +39,11 @@ pub(crate) fn restore_sidebar_window_sizes<C: NiriClient>(ctx: &mut Ctx<C>) -> R
             window,
             ctx.config.geometry.width,
             ctx.config.geometry.height,
+            screen.size(),
+        );
+        let (target_width, target_height) = (
+            screen.snap_size(target_width),
+            screen.snap_size(target_height),
         );
 
         match ctx.config.interaction.position {
@@ -87,7 +93,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         maximize(&mut ctx).expect("maximize failed");
@@ -114,7 +121,8 @@ mod tests {
             state: AppState::default(),
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         maximize(&mut ctx).expect("maximize should no-op");
@@ -145,7 +153,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         maximize(&mut ctx).expect("maximize toggle-off failed");
@@ -2,7 +2,7 @@ use crate::Ctx;
 use crate::commands::maximize::restore_sidebar_window_sizes;
 use crate::commands::reorder;
 use crate::niri::NiriClient;
-use crate::state::save_state;
+use crate::state::{save_preferences, save_state};
 use anyhow::Result;
 
 pub fn toggle_maximize_focus_mode<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
@@ -20,7 +20,8 @@ pub fn toggle_maximize_focus_mode<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()>
         ctx.state.maximized_window_id = Some(window.id);
     }
 
-    save_state(&ctx.state, &ctx.cache_dir)?;
+    save_state(&ctx.state, &ctx.runtime_dir)?;
+    save_preferences(&ctx.state, &ctx.state_dir)?;
     reorder(ctx)?;
     Ok(())
 }
@@ -64,7 +65,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_maximize_focus_mode(&mut ctx).expect("toggle should succeed");
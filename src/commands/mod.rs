@@ -2,20 +2,33 @@ mod close;
 mod flip;
 mod focus;
 mod hide;
+mod layout;
 mod listen;
 mod maximize;
 mod maximizefocusmode;
 mod movefrom;
+mod moveto;
+mod preset;
 mod reorder;
+mod replay;
+mod snapshot;
 mod togglewindow;
 
 pub use close::close;
 pub use flip::toggle_flip;
 pub use focus::focus;
 pub use hide::toggle_visibility;
-pub use listen::listen;
+pub use layout::{layout, render_plan};
+pub use listen::{
+    EventBurst, listen, process_burst, process_close, process_focus, process_move,
+    process_new_window,
+};
 pub use maximize::maximize;
 pub use maximizefocusmode::toggle_maximize_focus_mode;
 pub use movefrom::move_from;
-pub use reorder::reorder;
+pub use moveto::{MoveTarget, move_to};
+pub use preset::{apply_preset, cycle_preset, set_preset};
+pub use reorder::{plan_reorder, reorder, reorder_workspace};
+pub use replay::{ReplayedBurst, replay, replay_entries};
+pub use snapshot::{Snapshot, snapshot_list, snapshot_restore, snapshot_save};
 pub use togglewindow::toggle_window;
@@ -1,8 +1,12 @@
 use crate::{Ctx, NiriClient};
-use anyhow::Result;
-use niri_ipc::{Action, Window, WorkspaceReferenceArg};
-
-pub fn move_from<C: NiriClient>(ctx: &mut Ctx<C>, workspace: u64) -> Result<()> {
+use anyhow::{Context, Result};
+use niri_ipc::{Action, Window, Workspace, WorkspaceReferenceArg};
+
+pub fn move_from<C: NiriClient>(ctx: &mut Ctx<C>, source: WorkspaceReferenceArg) -> Result<()> {
+    let workspace = match source {
+        WorkspaceReferenceArg::Id(id) => id,
+        reference => resolve_workspace(&ctx.socket.get_workspaces()?, &reference)?,
+    };
     let active_workspace = ctx.socket.get_active_workspace()?.id;
     let windows = ctx.socket.get_windows()?;
 
@@ -13,12 +17,43 @@ pub fn move_from<C: NiriClient>(ctx: &mut Ctx<C>, workspace: u64) -> Result<()>
         })
         .collect();
 
-    move_to(ctx, windows_on_ws, active_workspace)?;
+    move_windows(ctx, windows_on_ws, active_workspace)?;
 
     Ok(())
 }
 
-pub fn move_to<C: NiriClient>(ctx: &mut Ctx<C>, windows: Vec<&Window>, to_ws: u64) -> Result<()> {
+/// Finds the workspace a reference points to, indices count on the focused output like in niri.
+pub fn resolve_workspace(
+    workspaces: &[Workspace],
+    reference: &WorkspaceReferenceArg,
+) -> Result<u64> {
+    let focused_output = workspaces
+        .iter()
+        .find(|w| w.is_focused)
+        .and_then(|w| w.output.as_ref());
+
+    let workspace = match reference {
+        WorkspaceReferenceArg::Id(id) => workspaces.iter().find(|w| w.id == *id),
+        WorkspaceReferenceArg::Index(idx) => workspaces
+            .iter()
+            .find(|w| w.idx == *idx && w.output.as_ref() == focused_output),
+        WorkspaceReferenceArg::Name(name) => workspaces.iter().find(|w| {
+            w.name
+                .as_ref()
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        }),
+    };
+
+    workspace
+        .map(|w| w.id)
+        .with_context(|| format!("Workspace {:?} not found", reference))
+}
+
+pub fn move_windows<C: NiriClient>(
+    ctx: &mut Ctx<C>,
+    windows: Vec<&Window>,
+    to_ws: u64,
+) -> Result<()> {
     for w in windows {
         ctx.socket.send_action(Action::MoveWindowToWorkspace {
             window_id: Some(w.id),
@@ -35,7 +70,7 @@ mod tests {
     use super::*;
     use crate::config::Config;
     use crate::state::{AppState, WindowState};
-    use crate::test_utils::{MockNiri, mock_window};
+    use crate::test_utils::{MockNiri, mock_window, mock_workspace};
     use niri_ipc::{Action, WorkspaceReferenceArg};
     use tempfile::tempdir;
 
@@ -87,10 +122,11 @@ mod tests {
             state,
             config: Config::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
-        move_from(&mut ctx, source_ws).expect("move_from failed");
+        move_from(&mut ctx, WorkspaceReferenceArg::Id(source_ws)).expect("move_from failed");
         let actions = &ctx.socket.sent_actions;
 
         // We expect exactly ONE action (for w100)
@@ -114,4 +150,57 @@ mod tests {
             panic!("Unexpected action type sent to socket");
         }
     }
+
+    #[test]
+    fn test_move_from_by_name() {
+        let temp_dir = tempdir().unwrap();
+        let mut state = AppState::default();
+        state.windows.push(WindowState {
+            id: 100,
+            width: 500,
+            height: 500,
+            is_floating: true,
+            position: None,
+        });
+
+        let mut mock = MockNiri::new(vec![mock_window(100, false, true, 2, None)]);
+        let mut chat = mock_workspace(2, 1, "eDP-1", false);
+        chat.name = Some("chat".into());
+        mock.workspaces.push(chat);
+
+        let mut ctx = Ctx {
+            state,
+            config: Config::default(),
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        move_from(&mut ctx, WorkspaceReferenceArg::Name("Chat".into())).expect("move_from failed");
+
+        assert!(matches!(
+            ctx.socket.sent_actions.as_slice(),
+            [Action::MoveWindowToWorkspace {
+                window_id: Some(100),
+                reference: WorkspaceReferenceArg::Id(1),
+                focus: false,
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_resolve_workspace_index_is_per_output() {
+        let workspaces = vec![
+            mock_workspace(1, 1, "eDP-1", false),
+            mock_workspace(2, 1, "HDMI-A-1", true),
+            mock_workspace(3, 2, "HDMI-A-1", false),
+        ];
+
+        let resolve = |reference| resolve_workspace(&workspaces, &reference).ok();
+        assert_eq!(resolve(WorkspaceReferenceArg::Index(1)), Some(2));
+        assert_eq!(resolve(WorkspaceReferenceArg::Index(2)), Some(3));
+        assert_eq!(resolve(WorkspaceReferenceArg::Id(1)), Some(1));
+        assert_eq!(resolve(WorkspaceReferenceArg::Id(9)), None);
+        assert_eq!(resolve(WorkspaceReferenceArg::Name("nope".into())), None);
+    }
 }
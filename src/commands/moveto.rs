@@ -0,0 +1,127 @@
+use crate::commands::movefrom::{move_windows, resolve_workspace};
+use crate::commands::reorder_workspace;
+use crate::{Ctx, NiriClient};
+use anyhow::{Context, Result};
+use niri_ipc::WorkspaceReferenceArg;
+
+pub enum MoveTarget {
+    Workspace(WorkspaceReferenceArg),
+    /// The workspace currently shown on this output
+    Output(String),
+}
+
+pub fn move_to<C: NiriClient>(ctx: &mut Ctx<C>, target: MoveTarget) -> Result<()> {
+    let workspaces = ctx.socket.get_workspaces()?;
+    let target_ws = match target {
+        MoveTarget::Workspace(reference) => resolve_workspace(&workspaces, &reference)?,
+        MoveTarget::Output(output) => workspaces
+            .iter()
+            .find(|w| w.is_active && w.output.as_deref() == Some(output.as_str()))
+            .map(|w| w.id)
+            .with_context(|| format!("No active workspace on output {}", output))?,
+    };
+
+    let windows = ctx.socket.get_windows()?;
+    let sidebar_windows = windows
+        .iter()
+        .filter(|w| {
+            w.workspace_id != Some(target_ws) && ctx.state.windows.iter().any(|ws| ws.id == w.id)
+        })
+        .collect();
+
+    move_windows(ctx, sidebar_windows, target_ws)?;
+    // The daemon only follows the focused workspace, and the target may be on another output
+    reorder_workspace(ctx, target_ws)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AppState, WindowState};
+    use crate::test_utils::{MockNiri, mock_config, mock_output, mock_window, mock_workspace};
+    use niri_ipc::Action;
+    use tempfile::{TempDir, tempdir};
+
+    fn tracked(id: u64) -> WindowState {
+        WindowState {
+            id,
+            width: 300,
+            height: 200,
+            is_floating: true,
+            position: None,
+        }
+    }
+
+    fn setup() -> (TempDir, Ctx<MockNiri>) {
+        let temp_dir = tempdir().unwrap();
+        let mut state = AppState::default();
+        state.windows.push(tracked(100));
+        state.windows.push(tracked(200));
+
+        let mut mock = MockNiri::new(vec![
+            mock_window(100, true, true, 1, None),
+            mock_window(200, false, true, 3, None),
+            mock_window(300, false, false, 1, None),
+        ]);
+        mock.workspaces
+            .push(mock_workspace(2, 1, "HDMI-A-1", false));
+        mock.workspaces[1].is_active = true;
+        mock.workspaces
+            .push(mock_workspace(3, 2, "HDMI-A-1", false));
+        mock.outputs.push(mock_output("HDMI-A-1", 1280, 720));
+
+        let ctx = Ctx {
+            state,
+            config: mock_config(),
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+        (temp_dir, ctx)
+    }
+
+    fn moved(ctx: &Ctx<MockNiri>) -> Vec<(u64, u64)> {
+        ctx.socket
+            .sent_actions
+            .iter()
+            .filter_map(|a| match a {
+                Action::MoveWindowToWorkspace {
+                    window_id: Some(window),
+                    reference: WorkspaceReferenceArg::Id(ws),
+                    focus: false,
+                } => Some((*window, *ws)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_move_to_output_uses_its_active_workspace() {
+        let (_temp_dir, mut ctx) = setup();
+
+        move_to(&mut ctx, MoveTarget::Output("HDMI-A-1".into())).expect("move_to failed");
+
+        assert_eq!(moved(&ctx), vec![(100, 2), (200, 2)]);
+    }
+
+    #[test]
+    fn test_move_to_workspace_skips_windows_already_there() {
+        let (_temp_dir, mut ctx) = setup();
+
+        move_to(
+            &mut ctx,
+            MoveTarget::Workspace(WorkspaceReferenceArg::Id(3)),
+        )
+        .expect("move_to failed");
+
+        assert_eq!(moved(&ctx), vec![(100, 3)]);
+    }
+
+    #[test]
+    fn test_move_to_unknown_output_fails() {
+        let (_temp_dir, mut ctx) = setup();
+
+        assert!(move_to(&mut ctx, MoveTarget::Output("DP-9".into())).is_err());
+        assert!(ctx.socket.sent_actions.is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+use crate::Ctx;
+use crate::commands::reorder;
+use crate::config::Config;
+use crate::niri::NiriClient;
+use crate::state::{AppState, save_preferences, save_state};
+use crate::window_rules::resolve_window_size;
+use anyhow::{Result, bail};
+use niri_ipc::{Action, SizeChange};
+
+/// The config with the state's active preset applied, a preset that went missing is skipped.
+pub fn apply_preset(base: &Config, state: &AppState) -> Config {
+    match base.with_preset(state.active_preset.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            crate::warn!("Ignoring active preset: {:#}", e);
+            base.clone()
+        }
+    }
+}
+
+/// `base` is the config without any preset, as presets don't stack.
+pub fn set_preset<C: NiriClient>(
+    ctx: &mut Ctx<C>,
+    base: &Config,
+    name: Option<&str>,
+) -> Result<()> {
+    if let Some(name) = name
+        && !base.preset.contains_key(name)
+    {
+        bail!("No preset named {:?}", name);
+    }
+
+    let config = base.with_preset(name)?;
+    ctx.state.active_preset = name.map(String::from);
+    ctx.config = config;
+    resize_sidebar_windows(ctx)?;
+    save_state(&ctx.state, &ctx.runtime_dir)?;
+    save_preferences(&ctx.state, &ctx.state_dir)?;
+    reorder(ctx)?;
+    Ok(())
+}
+
+/// A reorder only resizes along the stack, so new preset sizes are applied here first.
+fn resize_sidebar_windows<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
+    let windows = ctx.socket.get_windows()?;
+    let current_ws = ctx.socket.get_active_workspace()?.id;
+    let screen = ctx.socket.get_screen()?;
+
+    for window in windows.iter().filter(|w| {
+        w.workspace_id == Some(current_ws) && ctx.state.windows.iter().any(|ws| ws.id == w.id)
+    }) {
+        let (width, height) = resolve_window_size(
+            &ctx.config.window_rule,
+            window,
+            ctx.config.geometry.width,
+            ctx.config.geometry.height,
+            screen.size(),
+        );
+        let (width, height) = (screen.snap_size(width), screen.snap_size(height));
+        let _ = ctx.socket.send_action(Action::SetWindowWidth {
+            change: SizeChange::SetFixed(width),
+            id: Some(window.id),
+        });
+        let _ = ctx.socket.send_action(Action::SetWindowHeight {
+            change: SizeChange::SetFixed(height),
+            id: Some(window.id),
+        });
+    }
+    Ok(())
+}
+
+/// Switches to the next preset in alphabetical order, wrapping around.
+pub fn cycle_preset<C: NiriClient>(ctx: &mut Ctx<C>, base: &Config) -> Result<()> {
+    let names: Vec<_> = base.preset.keys().cloned().collect();
+    if names.is_empty() {
+        bail!("No presets configured");
+    }
+
+    let next = match &ctx.state.active_preset {
+        Some(current) => names
+            .iter()
+            .position(|n| n == current)
+            .map_or(0, |i| (i + 1) % names.len()),
+        None => 0,
+    };
+    set_preset(ctx, base, Some(&names[next]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Preset;
+    use crate::state::{WindowState, load_session_state, load_state};
+    use crate::test_utils::{MockNiri, mock_config, mock_window};
+    use niri_ipc::{Action, SizeChange};
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn presets() -> Config {
+        let mut config = mock_config();
+        for (name, width) in [("glance", 250), ("working", 600)] {
+            let geometry = toml::toml! { width = width };
+            config.preset.insert(
+                name.into(),
+                Preset {
+                    geometry: Some(geometry),
+                    ..Default::default()
+                },
+            );
+        }
+        config
+    }
+
+    fn ctx(temp_dir: &Path) -> Ctx<MockNiri> {
+        let mut state = AppState::default();
+        state.windows.push(WindowState {
+            id: 10,
+            width: 1000,
+            height: 800,
+            is_floating: true,
+            position: None,
+        });
+        Ctx {
+            state,
+            config: mock_config(),
+            socket: MockNiri::new(vec![mock_window(10, false, true, 1, Some((0.0, 0.0)))]),
+            runtime_dir: temp_dir.to_path_buf(),
+            state_dir: temp_dir.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn test_set_preset_persists_and_relayouts() {
+        let temp_dir = tempdir().unwrap();
+        let base = presets();
+        let mut ctx = ctx(temp_dir.path());
+
+        set_preset(&mut ctx, &base, Some("working")).expect("set_preset failed");
+
+        assert_eq!(ctx.state.active_preset.as_deref(), Some("working"));
+        let saved = load_state(temp_dir.path()).unwrap();
+        assert_eq!(saved.active_preset.as_deref(), Some("working"));
+        // Outlives the niri session, which gets a new runtime dir
+        let new_session = tempdir().unwrap();
+        let restarted = load_session_state(new_session.path(), temp_dir.path()).unwrap();
+        assert_eq!(restarted.active_preset.as_deref(), Some("working"));
+        assert!(restarted.windows.is_empty());
+        assert!(ctx.socket.sent_actions.iter().any(|a| matches!(
+            a,
+            Action::SetWindowWidth {
+                change: SizeChange::SetFixed(600),
+                id: Some(10)
+            }
+        )));
+    }
+
+    #[test]
+    fn test_set_unknown_preset_fails() {
+        let temp_dir = tempdir().unwrap();
+        let mut ctx = ctx(temp_dir.path());
+
+        assert!(set_preset(&mut ctx, &presets(), Some("nope")).is_err());
+        assert_eq!(ctx.state.active_preset, None);
+    }
+
+    #[test]
+    fn test_cycle_preset_wraps_around() {
+        let temp_dir = tempdir().unwrap();
+        let base = presets();
+        let mut ctx = ctx(temp_dir.path());
+
+        let mut seen = vec![];
+        for _ in 0..3 {
+            cycle_preset(&mut ctx, &base).expect("cycle_preset failed");
+            seen.push(ctx.state.active_preset.clone().unwrap());
+        }
+        assert_eq!(seen, vec!["glance", "working", "glance"]);
+    }
+
+    #[test]
+    fn test_apply_preset_skips_missing_preset() {
+        let base = presets();
+        let state = AppState {
+            active_preset: Some("removed".into()),
+            ..Default::default()
+        };
+
+        let config = apply_preset(&base, &state);
+        assert_eq!(config.geometry.width.resolve(1920), 300);
+    }
+}
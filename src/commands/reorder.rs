@@ -1,311 +1,109 @@
+use crate::Ctx;
 use crate::commands::maximize::restore_sidebar_window_sizes;
-use crate::config::{OverflowStrategy, SidebarPosition};
-use crate::niri::NiriClient;
+use crate::config::Margins;
+use crate::layout::{LayoutInput, LayoutPlan, Screen, needs_layers, plan_layout, resolve_margins};
+use crate::niri::{NiriClient, screen_of};
 use crate::state::save_state;
-use crate::window_rules::{resolve_rule_focus_peek, resolve_rule_peek, resolve_window_size};
-use crate::{Ctx, WindowTarget};
-use anyhow::Result;
-use niri_ipc::{Action, PositionChange, Window};
+use anyhow::{Context, Result};
+use niri_ipc::{Window, Workspace};
 use std::collections::HashSet;
 
-fn resolve_dimensions<C: NiriClient>(window: &Window, ctx: &Ctx<C>) -> WindowTarget {
-    let (width, height) = resolve_window_size(
-        &ctx.config.window_rule,
-        window,
-        ctx.config.geometry.width,
-        ctx.config.geometry.height,
-    );
-
-    WindowTarget { width, height }
+/// Everything queried from niri that goes into the layout.
+struct LayoutQuery {
+    screen: Screen,
+    workspace_id: u64,
+    windows: Vec<Window>,
+    margins: Margins,
 }
 
-fn calculate_coordinates<C: NiriClient>(
-    pos: SidebarPosition,
-    dims: WindowTarget,
-    screen: (i32, i32),
-    stack_offset: i32,
-    active_peek: i32,
-    ctx: &Ctx<C>,
-) -> (i32, i32) {
-    let state = &ctx.state;
-    let margins = &ctx.config.margins;
-    let (sw, sh) = screen;
-    let (w, h) = (dims.width, dims.height);
-
-    match pos {
-        SidebarPosition::Right => {
-            let visible_x = sw - w - margins.right;
-            let hidden_x = sw - active_peek;
-            let x = if state.is_hidden { hidden_x } else { visible_x };
-
-            let start_y = sh - h - margins.bottom;
-            let y = start_y - stack_offset;
-            (x, y)
-        }
-        SidebarPosition::Left => {
-            let visible_x = margins.left;
-            let hidden_x = -w + active_peek;
-            let x = if state.is_hidden { hidden_x } else { visible_x };
-
-            let start_y = sh - h - margins.bottom;
-            let y = start_y - stack_offset;
-            (x, y)
-        }
-        SidebarPosition::Bottom => {
-            let start_x = margins.left;
-            let x = start_x + stack_offset;
-
-            let visible_y = sh - h - margins.bottom;
-            let hidden_y = sh - active_peek;
-            let y = if state.is_hidden { hidden_y } else { visible_y };
-            (x, y)
-        }
-        SidebarPosition::Top => {
-            let start_x = margins.left;
-            let x = start_x + stack_offset;
-
-            let visible_y = margins.top;
-            let hidden_y = -h + active_peek;
-            let y = if state.is_hidden { hidden_y } else { visible_y };
-            (x, y)
-        }
+impl LayoutQuery {
+    fn run<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<Self> {
+        let screen = ctx.socket.get_screen()?;
+        let workspace = ctx.socket.get_active_workspace()?;
+        Self::run_on(ctx, workspace, screen)
     }
-}
-
-fn apply_maximize_sizes(
-    position: SidebarPosition,
-    dims: &mut [WindowTarget],
-    ordered_ids: &[u64],
-    maximized_window_id: Option<u64>,
-    display: (i32, i32),
-    margins: &crate::config::Margins,
-    gap: i32,
-) {
-    let Some(maximized_id) = maximized_window_id else {
-        return;
-    };
-
-    let Some(max_idx) = ordered_ids.iter().position(|id| *id == maximized_id) else {
-        return;
-    };
-
-    let count = dims.len();
-    if count == 0 {
-        return;
-    }
-
-    let gaps_total = gap * (count.saturating_sub(1) as i32);
-
-    match position {
-        SidebarPosition::Left | SidebarPosition::Right => {
-            let available =
-                (display.1 - margins.top - margins.bottom - gaps_total).max(count as i32);
-            if count == 1 {
-                dims[0].height = available;
-                return;
-            }
-
-            let mut max_h = (available * 70) / 100;
-            let mut other_h = ((available - max_h) / ((count - 1) as i32)).max(60);
-            if other_h * ((count - 1) as i32) >= available {
-                other_h = (available / (count as i32)).max(1);
-            }
-            max_h = available - other_h * ((count - 1) as i32);
-            if max_h < other_h {
-                max_h = other_h;
-            }
-
-            for (idx, dim) in dims.iter_mut().enumerate() {
-                dim.height = if idx == max_idx { max_h } else { other_h };
-            }
-        }
-        SidebarPosition::Top | SidebarPosition::Bottom => {
-            let available =
-                (display.0 - margins.left - margins.right - gaps_total).max(count as i32);
-            if count == 1 {
-                dims[0].width = available;
-                return;
-            }
-
-            let mut max_w = (available * 70) / 100;
-            let mut other_w = ((available - max_w) / ((count - 1) as i32)).max(60);
-            if other_w * ((count - 1) as i32) >= available {
-                other_w = (available / (count as i32)).max(1);
-            }
-            max_w = available - other_w * ((count - 1) as i32);
-            if max_w < other_w {
-                max_w = other_w;
-            }
-
-            for (idx, dim) in dims.iter_mut().enumerate() {
-                dim.width = if idx == max_idx { max_w } else { other_w };
-            }
-        }
-    }
-}
-
-fn is_vertical(position: SidebarPosition) -> bool {
-    matches!(position, SidebarPosition::Left | SidebarPosition::Right)
-}
-
-fn stack_axis_size(position: SidebarPosition, dims: WindowTarget) -> i32 {
-    if is_vertical(position) {
-        dims.height
-    } else {
-        dims.width
-    }
-}
-
-fn viewport_size(
-    position: SidebarPosition,
-    display: (i32, i32),
-    margins: &crate::config::Margins,
-) -> i32 {
-    if is_vertical(position) {
-        display.1 - margins.top - margins.bottom
-    } else {
-        display.0 - margins.left - margins.right
-    }
-    .max(0)
-}
-
-fn stack_length(position: SidebarPosition, dims: &[WindowTarget], gap: i32) -> i32 {
-    if dims.is_empty() {
-        return 0;
-    }
-
-    let windows_total: i32 = dims.iter().map(|dim| stack_axis_size(position, *dim)).sum();
-    windows_total + gap * (dims.len().saturating_sub(1) as i32)
-}
-
-fn stack_offset_before(
-    position: SidebarPosition,
-    dims: &[WindowTarget],
-    gap: i32,
-    index: usize,
-) -> i32 {
-    dims.iter()
-        .take(index)
-        .map(|dim| stack_axis_size(position, *dim) + gap)
-        .sum()
-}
-
-fn set_stack_axis_size(position: SidebarPosition, dims: &mut WindowTarget, size: i32) {
-    if is_vertical(position) {
-        dims.height = size;
-    } else {
-        dims.width = size;
-    }
-}
 
-fn shrink_to_viewport(
-    position: SidebarPosition,
-    dims: &mut [WindowTarget],
-    viewport: i32,
-    gap: i32,
-) {
-    if dims.is_empty() {
-        return;
+    /// Like [`LayoutQuery::run`], for a workspace that doesn't have to be the focused one.
+    fn run_for_workspace<C: NiriClient>(ctx: &mut Ctx<C>, workspace_id: u64) -> Result<Self> {
+        let workspace = ctx
+            .socket
+            .get_workspaces()?
+            .into_iter()
+            .find(|w| w.id == workspace_id)
+            .with_context(|| format!("Workspace {} not found", workspace_id))?;
+        let output_name = workspace
+            .output
+            .clone()
+            .with_context(|| format!("Workspace {} is not on an output", workspace_id))?;
+        let output = ctx
+            .socket
+            .get_outputs()?
+            .into_iter()
+            .find(|o| o.name == output_name)
+            .with_context(|| format!("Output {} not found", output_name))?;
+        let screen = screen_of(&output)?;
+        Self::run_on(ctx, workspace, screen)
     }
 
-    let gaps_total = gap * (dims.len().saturating_sub(1) as i32);
-    let available = viewport - gaps_total;
-    let current_total: i32 = dims.iter().map(|dim| stack_axis_size(position, *dim)).sum();
-
-    if available <= 0 || current_total <= available {
-        return;
-    }
-
-    if available < dims.len() as i32 {
-        for dim in dims {
-            set_stack_axis_size(position, dim, 1);
-        }
-        return;
-    }
-
-    let mut sizes: Vec<i32> = dims
-        .iter()
-        .map(|dim| ((stack_axis_size(position, *dim) * available) / current_total).max(1))
-        .collect();
-
-    while sizes.iter().sum::<i32>() > available {
-        if let Some(size) = sizes.iter_mut().rev().find(|size| **size > 1) {
-            *size -= 1;
+    fn run_on<C: NiriClient>(
+        ctx: &mut Ctx<C>,
+        workspace: Workspace,
+        screen: Screen,
+    ) -> Result<Self> {
+        let windows = ctx.socket.get_windows()?;
+        let layers = if needs_layers(&ctx.config) {
+            ctx.socket.get_layers()?
         } else {
-            break;
-        }
-    }
+            vec![]
+        };
+        let margins = resolve_margins(&ctx.config, workspace.output.as_deref(), &layers);
 
-    let mut total = sizes.iter().sum::<i32>();
-    while total < available {
-        for size in sizes.iter_mut() {
-            if total >= available {
-                break;
-            }
-            *size += 1;
-            total += 1;
-        }
+        Ok(Self {
+            screen,
+            workspace_id: workspace.id,
+            windows,
+            margins,
+        })
     }
 
-    for (dim, size) in dims.iter_mut().zip(sizes) {
-        set_stack_axis_size(position, dim, size);
+    fn plan<C: NiriClient>(&self, ctx: &Ctx<C>) -> LayoutPlan {
+        plan_layout(&LayoutInput {
+            windows: &self.windows,
+            workspace_id: self.workspace_id,
+            screen: self.screen,
+            margins: self.margins,
+            config: &ctx.config,
+            state: &ctx.state,
+        })
     }
 }
 
-fn resolve_scroll_offset<C: NiriClient>(
-    ctx: &Ctx<C>,
-    position: SidebarPosition,
-    dims: &[WindowTarget],
-    sidebar_windows: &[&Window],
-    viewport: i32,
-    gap: i32,
-) -> i32 {
-    let max_scroll = (stack_length(position, dims, gap) - viewport).max(0);
-    if max_scroll == 0 {
-        return 0;
-    }
+pub fn reorder<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
+    let query = LayoutQuery::run(ctx)?;
+    apply_layout(ctx, query)
+}
 
-    let mut scroll_offset = ctx.state.scroll_offset.clamp(0, max_scroll);
-    let Some(focused_index) = sidebar_windows.iter().position(|w| w.is_focused) else {
-        return scroll_offset;
-    };
-
-    let before = stack_offset_before(position, dims, gap, focused_index);
-    let size = stack_axis_size(position, dims[focused_index]);
-    let min_visible_offset = (before + size - viewport).clamp(0, max_scroll);
-    let max_visible_offset = before.clamp(0, max_scroll);
-
-    if min_visible_offset > max_visible_offset {
-        min_visible_offset
-    } else {
-        if scroll_offset < min_visible_offset {
-            scroll_offset = min_visible_offset;
-        }
-        if scroll_offset > max_visible_offset {
-            scroll_offset = max_visible_offset;
-        }
-        scroll_offset
-    }
+/// Lays the sidebar out on a workspace that may be on another output than the focused one.
+pub fn reorder_workspace<C: NiriClient>(ctx: &mut Ctx<C>, workspace_id: u64) -> Result<()> {
+    let query = LayoutQuery::run_for_workspace(ctx, workspace_id)?;
+    apply_layout(ctx, query)
 }
 
-pub fn reorder<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
-    let (display_w, display_h) = ctx.socket.get_screen_dimensions()?;
-    let current_ws = ctx.socket.get_active_workspace()?.id;
-    let all_windows = ctx.socket.get_windows()?;
-
-    let sidebar_ids: Vec<u64> = ctx.state.windows.iter().map(|w| w.id).collect();
-    let mut sidebar_windows: Vec<_> = all_windows
-        .iter()
-        .filter(|w| {
-            w.is_floating && w.workspace_id == Some(current_ws) && sidebar_ids.contains(&w.id)
-        })
-        .collect();
+fn apply_layout<C: NiriClient>(ctx: &mut Ctx<C>, query: LayoutQuery) -> Result<()> {
+    let plan = query.plan(ctx);
+    let all_windows = query.windows;
 
     let initial_len = ctx.state.windows.len();
     let active_ids: HashSet<u64> = all_windows.iter().map(|w| w.id).collect();
 
     ctx.state.windows.retain(|w| active_ids.contains(&w.id));
     let mut state_changed = ctx.state.windows.len() != initial_len;
+    let sidebar_ids: HashSet<u64> = ctx.state.windows.iter().map(|w| w.id).collect();
+    let origins_len = ctx.state.tiled_origins.len();
+    ctx.state
+        .tiled_origins
+        .retain(|id, _| sidebar_ids.contains(id));
+    state_changed |= ctx.state.tiled_origins.len() != origins_len;
     let mut cleared_maximized = false;
     if let Some(id) = ctx.state.maximized_window_id
         && !ctx.state.windows.iter().any(|w| w.id == id)
@@ -317,135 +115,30 @@ pub fn reorder<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
     if cleared_maximized {
         restore_sidebar_window_sizes(ctx)?;
     }
-    if state_changed {
-        save_state(&ctx.state, &ctx.cache_dir)?;
-    }
-
-    // Sort by ID for stable ordering
-    sidebar_windows.sort_by_key(|w| {
-        sidebar_ids
-            .iter()
-            .position(|id| *id == w.id)
-            .unwrap_or(usize::MAX)
-    });
-    if ctx.state.is_flipped {
-        sidebar_windows.reverse();
-    }
-
-    let position = ctx.config.interaction.position;
-    let gap = ctx.config.geometry.gap;
-    let focused_sidebar_id = sidebar_windows.iter().find(|w| w.is_focused).map(|w| w.id);
-    let active_maximized_window = match (ctx.state.is_hidden, ctx.state.maximized_window_id) {
-        (false, maximized) => maximized,
-        (true, Some(maximized_id)) if focused_sidebar_id == Some(maximized_id) => {
-            Some(maximized_id)
-        }
-        _ => None,
-    };
-    let mut dims: Vec<WindowTarget> = sidebar_windows
-        .iter()
-        .map(|window| resolve_dimensions(window, ctx))
-        .collect();
-    let ordered_ids: Vec<u64> = sidebar_windows.iter().map(|window| window.id).collect();
-    let maximized_in_current_layout = ctx
-        .state
-        .maximized_window_id
-        .is_some_and(|id| ordered_ids.contains(&id));
-
-    apply_maximize_sizes(
-        position,
-        &mut dims,
-        &ordered_ids,
-        active_maximized_window,
-        (display_w, display_h),
-        &ctx.config.margins,
-        gap,
-    );
-    let viewport = viewport_size(position, (display_w, display_h), &ctx.config.margins);
-    if ctx.config.geometry.overflow == OverflowStrategy::Shrink {
-        shrink_to_viewport(position, &mut dims, viewport, gap);
+    if ctx.state.scroll_offset != plan.scroll_offset {
+        ctx.state.scroll_offset = plan.scroll_offset;
+        state_changed = true;
     }
-    let scroll_offset = if ctx.config.geometry.overflow == OverflowStrategy::Scroll {
-        resolve_scroll_offset(ctx, position, &dims, &sidebar_windows, viewport, gap)
-    } else {
-        0
-    };
-    if ctx.state.scroll_offset != scroll_offset {
-        ctx.state.scroll_offset = scroll_offset;
-        save_state(&ctx.state, &ctx.cache_dir)?;
+    if state_changed {
+        save_state(&ctx.state, &ctx.runtime_dir)?;
     }
-    let mut current_stack_offset = 0;
-
-    for (index, window) in sidebar_windows.iter().enumerate() {
-        let dims = dims[index];
-
-        let active_peek = if window.is_focused {
-            resolve_rule_focus_peek(
-                &ctx.config.window_rule,
-                window,
-                ctx.config.interaction.get_focus_peek(),
-            )
-        } else {
-            resolve_rule_peek(&ctx.config.window_rule, window, ctx.config.interaction.peek)
-        };
-
-        let (mut target_x, mut target_y) = calculate_coordinates(
-            position,
-            dims,
-            (display_w, display_h),
-            current_stack_offset,
-            active_peek,
-            ctx,
-        );
-        match position {
-            SidebarPosition::Left | SidebarPosition::Right => {
-                target_y += scroll_offset;
-            }
-            SidebarPosition::Top | SidebarPosition::Bottom => {
-                target_x -= scroll_offset;
-            }
-        }
-
-        match position {
-            SidebarPosition::Left | SidebarPosition::Right => {
-                current_stack_offset += dims.height + gap;
-            }
-            SidebarPosition::Top | SidebarPosition::Bottom => {
-                current_stack_offset += dims.width + gap;
-            }
-        }
-
-        if maximized_in_current_layout || ctx.config.geometry.overflow == OverflowStrategy::Shrink {
-            match position {
-                SidebarPosition::Left | SidebarPosition::Right => {
-                    let _ = ctx.socket.send_action(Action::SetWindowHeight {
-                        change: niri_ipc::SizeChange::SetFixed(dims.height),
-                        id: Some(window.id),
-                    });
-                }
-                SidebarPosition::Top | SidebarPosition::Bottom => {
-                    let _ = ctx.socket.send_action(Action::SetWindowWidth {
-                        change: niri_ipc::SizeChange::SetFixed(dims.width),
-                        id: Some(window.id),
-                    });
-                }
-            }
-        }
 
-        let _ = ctx.socket.send_action(Action::MoveFloatingWindow {
-            id: Some(window.id),
-            x: PositionChange::SetFixed(target_x.into()),
-            y: PositionChange::SetFixed(target_y.into()),
-        });
+    for action in plan.actions {
+        let _ = ctx.socket.send_action(action);
     }
 
     Ok(())
 }
 
+/// Queries niri for everything a reorder needs and plans it, without touching state or windows.
+pub fn plan_reorder<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<LayoutPlan> {
+    Ok(LayoutQuery::run(ctx)?.plan(ctx))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::WindowRule;
+    use crate::config::{OverflowStrategy, SidebarPosition, WindowRule};
     use crate::state::{AppState, WindowState};
     use crate::test_utils::{MockNiri, mock_config, mock_window};
     use niri_ipc::{Action, PositionChange};
@@ -483,7 +176,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -549,7 +243,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -600,7 +295,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -648,7 +344,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("reorder failed");
@@ -696,7 +393,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -748,7 +446,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -800,7 +499,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -871,7 +571,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).unwrap();
@@ -945,7 +646,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).unwrap();
@@ -968,6 +670,105 @@ mod tests {
         )));
     }
 
+    #[test]
+    fn test_skips_actions_for_windows_already_in_place() {
+        let temp_dir = tempdir().unwrap();
+        // Bottom slot is at (1600, 830), the slot above at (1600, 620)
+        let mut w1 = mock_window(1, false, true, 1, Some((1600.0, 830.0)));
+        w1.layout.window_size = (300, 200);
+        let w2 = mock_window(2, true, true, 1, Some((1.0, 2.0)));
+        let mock = MockNiri::new(vec![w1, w2]);
+
+        let mut state = AppState::default();
+        for id in [1, 2] {
+            state.windows.push(WindowState {
+                id,
+                width: 300,
+                height: 200,
+                is_floating: true,
+                position: Some((1.0, 2.0)),
+            });
+        }
+
+        let mut config = mock_config();
+        config.geometry.overflow = OverflowStrategy::Shrink;
+        let mut ctx = Ctx {
+            state,
+            config,
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        reorder(&mut ctx).expect("Reorder failed");
+
+        let actions = &ctx.socket.sent_actions;
+        assert!(!actions.iter().any(|a| matches!(
+            a,
+            Action::MoveFloatingWindow { id: Some(1), .. }
+                | Action::SetWindowHeight { id: Some(1), .. }
+        )));
+        assert!(
+            actions
+                .iter()
+                .any(|a| matches!(a, Action::MoveFloatingWindow { id: Some(2), .. }))
+        );
+    }
+
+    #[test]
+    fn test_plan_reorder_does_not_touch_windows_or_state() {
+        let temp_dir = tempdir().unwrap();
+        let w1 = mock_window(1, true, true, 1, Some((1.0, 2.0)));
+        let mock = MockNiri::new(vec![w1]);
+
+        let mut state = AppState {
+            scroll_offset: 100,
+            ..Default::default()
+        };
+        state.windows.push(WindowState {
+            id: 1,
+            width: 300,
+            height: 200,
+            is_floating: true,
+            position: Some((1.0, 2.0)),
+        });
+        // Window 2 no longer exists, a real reorder would drop it
+        state.windows.push(WindowState {
+            id: 2,
+            width: 300,
+            height: 200,
+            is_floating: true,
+            position: Some((1.0, 2.0)),
+        });
+
+        let mut ctx = Ctx {
+            state,
+            config: mock_config(),
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        let plan = plan_reorder(&mut ctx).expect("Planning failed");
+
+        assert!(ctx.socket.sent_actions.is_empty());
+        assert_eq!(ctx.state.windows.len(), 2);
+        assert_eq!(ctx.state.scroll_offset, 100);
+        assert!(!temp_dir.path().join("state.json").exists());
+
+        assert_eq!(plan.scroll_offset, 0);
+        assert_eq!(plan.windows.len(), 1);
+        assert_eq!((plan.windows[0].x, plan.windows[0].y), (1600.0, 830.0));
+        assert!(plan.actions.iter().any(|a| matches!(
+            a,
+            Action::MoveFloatingWindow {
+                id: Some(1),
+                y: PositionChange::SetFixed(830.0),
+                ..
+            }
+        )));
+    }
+
     #[test]
     fn test_vertical_overflow_scrolls_focused_window_into_view() {
         let temp_dir = tempdir().unwrap();
@@ -990,7 +791,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1034,7 +836,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1079,7 +882,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1120,7 +924,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1156,14 +961,15 @@ mod tests {
         let mock = MockNiri::new(windows);
         let mut config = mock_config();
         config.interaction.position = SidebarPosition::Bottom;
-        config.geometry.width = 300;
+        config.geometry.width = 300.into();
         config.geometry.gap = 10;
 
         let mut ctx = Ctx {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1199,7 +1005,7 @@ mod tests {
         let mock = MockNiri::new(windows);
         let mut config = mock_config();
         config.interaction.position = SidebarPosition::Bottom;
-        config.geometry.width = 300;
+        config.geometry.width = 300.into();
         config.geometry.gap = 10;
         config.geometry.overflow = OverflowStrategy::Shrink;
 
@@ -1207,7 +1013,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1238,7 +1045,7 @@ mod tests {
         let mock = MockNiri::new(vec![w1, w2]);
 
         let mut config = mock_config();
-        config.geometry.height = 1200;
+        config.geometry.height = 1200.into();
 
         let mut state = AppState::default();
         state.windows.push(WindowState {
@@ -1260,7 +1067,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1301,7 +1109,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1329,7 +1138,7 @@ mod tests {
         let mut config = mock_config();
         config.interaction.position = SidebarPosition::Left;
         config.interaction.peek = 10;
-        config.geometry.width = 300;
+        config.geometry.width = 300.into();
         config.margins.left = 0;
 
         let mut state = AppState {
@@ -1350,7 +1159,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1376,7 +1186,7 @@ mod tests {
 
         let mut config = mock_config();
         config.interaction.position = SidebarPosition::Bottom;
-        config.geometry.width = 100;
+        config.geometry.width = 100.into();
         config.geometry.gap = 10;
         config.margins.left = 20;
 
@@ -1403,7 +1213,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1437,12 +1248,12 @@ mod tests {
 
         let mut config = mock_config();
         config.interaction.position = SidebarPosition::Right;
-        config.geometry.width = 300;
+        config.geometry.width = 300.into();
         config.interaction.peek = 10;
 
         config.window_rule = vec![WindowRule {
             app_id: Some(Regex::new("special").unwrap()),
-            width: Some(500),
+            width: Some(500.into()),
             peek: Some(100),
             ..Default::default()
         }];
@@ -1473,7 +1284,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1520,12 +1332,12 @@ mod tests {
         let mut config = mock_config();
         config.interaction.position = SidebarPosition::Left;
         config.interaction.peek = 10;
-        config.geometry.width = 300;
+        config.geometry.width = 300.into();
         config.margins.left = 0;
 
         config.window_rule = vec![WindowRule {
             app_id: Some(Regex::new("special").unwrap()),
-            width: Some(400),
+            width: Some(400.into()),
             peek: Some(50),
             ..Default::default()
         }];
@@ -1555,7 +1367,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1597,13 +1410,13 @@ mod tests {
 
         let mut config = mock_config();
         config.interaction.position = SidebarPosition::Bottom;
-        config.geometry.width = 100;
+        config.geometry.width = 100.into();
         config.geometry.gap = 10;
         config.margins.left = 0;
 
         config.window_rule = vec![WindowRule {
             app_id: Some(Regex::new("wide").unwrap()),
-            width: Some(200),
+            width: Some(200.into()),
             ..Default::default()
         }];
 
@@ -1630,7 +1443,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         reorder(&mut ctx).expect("Reorder failed");
@@ -1672,14 +1486,14 @@ mod tests {
         let mock = MockNiri::new(vec![w1, w2, w3]);
         let mut config = mock_config();
         config.interaction.position = SidebarPosition::Right;
-        config.geometry.height = 200;
+        config.geometry.height = 200.into();
         config.geometry.gap = 10;
         config.margins.top = 0;
         config.margins.right = 0;
         config.margins.bottom = 0;
         config.window_rule = vec![WindowRule {
             app_id: Some(Regex::new("tall").unwrap()),
-            height: Some(400),
+            height: Some(400.into()),
             ..Default::default()
         }];
         let mut state = AppState::default();
@@ -1711,7 +1525,8 @@ mod tests {
             state,
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
         reorder(&mut ctx).expect("Reorder failed");
         let actions = &ctx.socket.sent_actions;
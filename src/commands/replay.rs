@@ -0,0 +1,257 @@
+use crate::commands::listen::{EventBurst, process_burst};
+use crate::commands::reorder;
+use crate::niri::EventStreamClient;
+use crate::recording::{Entry, Query, ReplayClient, load_recording, same_action};
+use crate::{AppState, Config, Ctx};
+use anyhow::{Result, bail};
+use niri_ipc::Action;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+/// The actions one recorded burst sent back then, and the ones it sends now.
+#[derive(Debug)]
+pub struct ReplayedBurst {
+    /// Index of the burst's entry in the recording
+    pub entry: usize,
+    pub recorded: Vec<Action>,
+    pub replayed: Vec<Action>,
+    pub error: Option<String>,
+}
+
+impl ReplayedBurst {
+    pub fn matches(&self) -> bool {
+        self.recorded.len() == self.replayed.len()
+            && self
+                .recorded
+                .iter()
+                .zip(&self.replayed)
+                .all(|(a, b)| same_action(a, b))
+    }
+}
+
+/// Replays a `listen --record` file and prints every burst that sends different actions now.
+pub fn replay(path: &Path) -> Result<()> {
+    let entries = load_recording(path)?;
+    let runtime_dir =
+        std::env::temp_dir().join(format!("niri-sidebar-replay-{}", std::process::id()));
+    fs::create_dir_all(&runtime_dir)?;
+    let result = replay_entries(entries, &runtime_dir);
+    let _ = fs::remove_dir_all(&runtime_dir);
+
+    let bursts = result?;
+    let differing: Vec<_> = bursts.iter().filter(|b| !b.matches()).collect();
+    for burst in &differing {
+        println!("{}", render_diff(burst)?);
+    }
+    if !differing.is_empty() {
+        bail!(
+            "{} of {} bursts sent different actions",
+            differing.len(),
+            bursts.len()
+        );
+    }
+    println!(
+        "Replayed {} bursts, all sent the recorded actions",
+        bursts.len()
+    );
+    Ok(())
+}
+
+/// Runs every recorded burst through the same handlers as the daemon, against the recorded niri
+/// responses. State saved along the way goes to `runtime_dir`.
+pub fn replay_entries(entries: Vec<Entry>, runtime_dir: &Path) -> Result<Vec<ReplayedBurst>> {
+    let mut ctx = Ctx {
+        state: AppState::default(),
+        config: Config::default(),
+        socket: EventStreamClient::new(ReplayClient::default()),
+        runtime_dir: runtime_dir.to_path_buf(),
+        state_dir: runtime_dir.to_path_buf(),
+    };
+    let mut bursts = vec![];
+
+    for (index, start, responses, recorded) in group_entries(entries) {
+        let result = match start {
+            Entry::Resync { state, config } => {
+                ctx.socket = EventStreamClient::new(ReplayClient::default());
+                ctx.state = state;
+                ctx.config = config;
+                ctx.socket.inner_mut().load(responses, recorded.clone());
+                reorder(&mut ctx)
+            }
+            Entry::Burst {
+                events,
+                state,
+                config,
+                outputs_changed,
+            } => {
+                ctx.state = state;
+                ctx.config = config;
+                ctx.socket.inner_mut().load(responses, recorded.clone());
+                let mut burst = EventBurst {
+                    outputs_changed,
+                    ..Default::default()
+                };
+                for event in events {
+                    burst.push(&event);
+                    ctx.socket.apply(event);
+                }
+                ctx.socket.mark_synced();
+                process_burst(&mut ctx, &burst)
+            }
+            _ => unreachable!("groups start with a burst or resync"),
+        };
+
+        bursts.push(ReplayedBurst {
+            entry: index,
+            recorded: recorded.into_iter().map(|(action, _)| action).collect(),
+            replayed: std::mem::take(&mut ctx.socket.inner_mut().sent_actions),
+            error: result.err().map(|e| format!("{:#}", e)),
+        });
+    }
+    Ok(bursts)
+}
+
+type Responses = VecDeque<(Query, Result<Value, String>)>;
+type RecordedActions = Vec<(Action, Option<String>)>;
+
+/// Splits a recording into bursts and resyncs with the queries and actions that followed them.
+/// Anything before the first one happened while the daemon started up.
+fn group_entries(entries: Vec<Entry>) -> Vec<(usize, Entry, Responses, RecordedActions)> {
+    let mut groups: Vec<(usize, Entry, Responses, RecordedActions)> = vec![];
+    for (index, entry) in entries.into_iter().enumerate() {
+        match entry {
+            Entry::Query { query, response } => {
+                if let Some((_, _, responses, _)) = groups.last_mut() {
+                    responses.push_back((query, response));
+                }
+            }
+            Entry::Action { action, error } => {
+                if let Some((_, _, _, actions)) = groups.last_mut() {
+                    actions.push((action, error));
+                }
+            }
+            start => groups.push((index, start, VecDeque::new(), vec![])),
+        }
+    }
+    groups
+}
+
+fn render_diff(burst: &ReplayedBurst) -> Result<String> {
+    let mut out = format!("Entry {} sent different actions:\n", burst.entry + 1);
+    let common = burst
+        .recorded
+        .iter()
+        .zip(&burst.replayed)
+        .take_while(|(a, b)| same_action(a, b))
+        .count();
+    for action in &burst.recorded[..common] {
+        out.push_str(&format!("  {}\n", serde_json::to_string(action)?));
+    }
+    for action in &burst.recorded[common..] {
+        out.push_str(&format!("- {}\n", serde_json::to_string(action)?));
+    }
+    for action in &burst.replayed[common..] {
+        out.push_str(&format!("+ {}\n", serde_json::to_string(action)?));
+    }
+    if let Some(error) = &burst.error {
+        out.push_str(&format!("! {}\n", error));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::{Recorder, RecordingClient};
+    use crate::state::WindowState;
+    use crate::test_utils::{MockNiri, mock_config, mock_window};
+    use niri_ipc::Event;
+    use tempfile::tempdir;
+
+    fn tracked(id: u64) -> WindowState {
+        WindowState {
+            id,
+            width: 300,
+            height: 200,
+            is_floating: true,
+            position: None,
+        }
+    }
+
+    /// Records a window of the sidebar closing, the way the daemon would.
+    fn record_close(path: &Path, runtime_dir: &Path) {
+        let recorder = Recorder::create(path).unwrap();
+        let windows = vec![
+            mock_window(1, false, true, 1, Some((0.0, 0.0))),
+            mock_window(2, true, true, 1, Some((0.0, 0.0))),
+            mock_window(3, false, true, 1, Some((0.0, 0.0))),
+        ];
+        let mock = MockNiri::new(windows[..2].to_vec());
+        let workspaces = mock.workspaces.clone();
+        let mut ctx = Ctx {
+            state: AppState {
+                windows: vec![tracked(1), tracked(2), tracked(3)],
+                ..Default::default()
+            },
+            config: mock_config(),
+            socket: EventStreamClient::new(RecordingClient::new(mock, Some(recorder.clone()))),
+            runtime_dir: runtime_dir.to_path_buf(),
+            state_dir: runtime_dir.to_path_buf(),
+        };
+
+        // The stream starts with the full window and workspace lists
+        let events = vec![
+            Event::WindowsChanged { windows },
+            Event::WorkspacesChanged { workspaces },
+            Event::WindowClosed { id: 3 },
+        ];
+        recorder.record(&Entry::Burst {
+            events: events.clone(),
+            state: ctx.state.clone(),
+            config: ctx.config.clone(),
+            outputs_changed: false,
+        });
+        let mut burst = EventBurst::default();
+        for event in events {
+            burst.push(&event);
+            ctx.socket.apply(event);
+        }
+        ctx.socket.mark_synced();
+        process_burst(&mut ctx, &burst).unwrap();
+    }
+
+    #[test]
+    fn test_replay_sends_the_recorded_actions() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("recording.jsonl");
+        record_close(&path, temp_dir.path());
+
+        let bursts = replay_entries(load_recording(&path).unwrap(), temp_dir.path()).unwrap();
+
+        assert_eq!(bursts.len(), 1);
+        assert!(!bursts[0].recorded.is_empty());
+        assert!(bursts[0].matches(), "{}", render_diff(&bursts[0]).unwrap());
+        assert!(bursts[0].error.is_none());
+    }
+
+    #[test]
+    fn test_replay_reports_changed_behavior() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("recording.jsonl");
+        record_close(&path, temp_dir.path());
+
+        // As if the sidebar had been configured differently back then
+        let mut entries = load_recording(&path).unwrap();
+        if let Entry::Burst { config, .. } = &mut entries[0] {
+            config.geometry.gap += 50;
+        }
+        let bursts = replay_entries(entries, temp_dir.path()).unwrap();
+
+        assert!(!bursts[0].matches());
+        let diff = render_diff(&bursts[0]).unwrap();
+        assert!(diff.lines().any(|l| l.starts_with("- ")));
+        assert!(diff.lines().any(|l| l.starts_with("+ ")));
+    }
+}
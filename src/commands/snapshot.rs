@@ -0,0 +1,313 @@
+use crate::Ctx;
+use crate::commands::movefrom::move_windows;
+use crate::commands::reorder;
+use crate::commands::togglewindow::add_to_sidebar;
+use crate::niri::NiriClient;
+use crate::state::{lock_state_dir, save_preferences, save_state, write_atomic};
+use anyhow::{Context, Result};
+use niri_ipc::Window;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A saved sidebar arrangement. Window ids don't survive restarts, so windows are remembered by
+/// app id and title instead.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Snapshot {
+    pub windows: Vec<Fingerprint>,
+    /// Index into `windows`
+    pub maximized: Option<usize>,
+    pub is_hidden: bool,
+    pub is_flipped: bool,
+    pub scroll_offset: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Fingerprint {
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+}
+
+impl Fingerprint {
+    fn of(window: &Window) -> Self {
+        Self {
+            app_id: window.app_id.clone(),
+            title: window.title.clone(),
+        }
+    }
+}
+
+pub fn snapshots_path(base_dir: &Path) -> PathBuf {
+    base_dir.join("snapshots.json")
+}
+
+pub fn load_snapshots(base_dir: &Path) -> Result<BTreeMap<String, Snapshot>> {
+    let path = snapshots_path(base_dir);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content = fs::read_to_string(&path)?;
+    serde_json::from_str(&content).with_context(|| format!("Could not read {:?}", path))
+}
+
+fn save_snapshots(snapshots: &BTreeMap<String, Snapshot>, base_dir: &Path) -> Result<()> {
+    let content = serde_json::to_string_pretty(snapshots)?;
+    write_atomic(&snapshots_path(base_dir), &content)
+}
+
+pub fn snapshot_save<C: NiriClient>(ctx: &mut Ctx<C>, name: &str) -> Result<()> {
+    let windows = ctx.socket.get_windows()?;
+    let mut snapshot = Snapshot {
+        windows: vec![],
+        maximized: None,
+        is_hidden: ctx.state.is_hidden,
+        is_flipped: ctx.state.is_flipped,
+        scroll_offset: ctx.state.scroll_offset,
+    };
+    for tracked in &ctx.state.windows {
+        let Some(window) = windows.iter().find(|w| w.id == tracked.id) else {
+            continue;
+        };
+        if ctx.state.maximized_window_id == Some(window.id) {
+            snapshot.maximized = Some(snapshot.windows.len());
+        }
+        snapshot.windows.push(Fingerprint::of(window));
+    }
+
+    // Another niri session may save a snapshot at the same time
+    let _lock = lock_state_dir(&ctx.state_dir)?;
+    let mut snapshots = load_snapshots(&ctx.state_dir)?;
+    println!(
+        "Saved {} sidebar windows as snapshot {:?}",
+        snapshot.windows.len(),
+        name
+    );
+    snapshots.insert(name.to_string(), snapshot);
+    save_snapshots(&snapshots, &ctx.state_dir)
+}
+
+/// Re-adopts the open windows matching a snapshot in its order, in front of any other windows
+/// still in the sidebar.
+pub fn snapshot_restore<C: NiriClient>(ctx: &mut Ctx<C>, name: &str) -> Result<()> {
+    let snapshots = load_snapshots(&ctx.state_dir)?;
+    let snapshot = snapshots
+        .get(name)
+        .with_context(|| format!("No snapshot named {:?}", name))?;
+
+    let windows = ctx.socket.get_windows()?;
+    let matched = match_windows(&snapshot.windows, &windows);
+    let active_ws = ctx.socket.get_active_workspace()?.id;
+
+    let mut previous = std::mem::take(&mut ctx.state.windows);
+    ctx.state.maximized_window_id = None;
+    for window in matched.iter().flatten() {
+        ctx.state.ignored_windows.retain(|id| *id != window.id);
+        match previous.iter().position(|w| w.id == window.id) {
+            Some(index) => ctx.state.windows.push(previous.remove(index)),
+            None => add_to_sidebar(ctx, window)?,
+        }
+    }
+    ctx.state.windows.append(&mut previous);
+
+    ctx.state.maximized_window_id = snapshot
+        .maximized
+        .and_then(|index| matched.get(index).copied().flatten())
+        .map(|w| w.id);
+    ctx.state.is_hidden = snapshot.is_hidden;
+    ctx.state.is_flipped = snapshot.is_flipped;
+    ctx.state.scroll_offset = snapshot.scroll_offset;
+
+    let elsewhere = matched
+        .iter()
+        .flatten()
+        .filter(|w| w.workspace_id != Some(active_ws))
+        .copied()
+        .collect();
+    move_windows(ctx, elsewhere, active_ws)?;
+
+    save_state(&ctx.state, &ctx.runtime_dir)?;
+    save_preferences(&ctx.state, &ctx.state_dir)?;
+    reorder(ctx)?;
+    Ok(())
+}
+
+pub fn snapshot_list<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
+    for (name, snapshot) in load_snapshots(&ctx.state_dir)? {
+        println!("{}\t{} windows", name, snapshot.windows.len());
+    }
+    Ok(())
+}
+
+/// Finds an open window for every fingerprint, preferring exact matches over app id only ones
+/// as titles tend to change. Each window is used at most once.
+fn match_windows<'a>(
+    fingerprints: &[Fingerprint],
+    windows: &'a [Window],
+) -> Vec<Option<&'a Window>> {
+    let mut matched: Vec<Option<&Window>> = vec![None; fingerprints.len()];
+    let mut used = vec![false; windows.len()];
+
+    let passes: [fn(&Fingerprint, &Window) -> bool; 2] = [
+        |f, w| f.app_id == w.app_id && f.title == w.title,
+        |f, w| f.app_id.is_some() && f.app_id == w.app_id,
+    ];
+    for is_match in passes {
+        for (slot, fingerprint) in matched.iter_mut().zip(fingerprints) {
+            if slot.is_some() {
+                continue;
+            }
+            if let Some(index) =
+                (0..windows.len()).find(|i| !used[*i] && is_match(fingerprint, &windows[*i]))
+            {
+                used[index] = true;
+                *slot = Some(&windows[index]);
+            }
+        }
+    }
+
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AppState, WindowState};
+    use crate::test_utils::{MockNiri, mock_config, mock_window};
+    use niri_ipc::{Action, WorkspaceReferenceArg};
+    use tempfile::tempdir;
+
+    fn app_window(id: u64, app_id: &str, title: &str, workspace: u64) -> Window {
+        let mut window = mock_window(id, false, true, workspace, Some((0.0, 0.0)));
+        window.app_id = Some(app_id.into());
+        window.title = Some(title.into());
+        window
+    }
+
+    fn tracked(id: u64) -> WindowState {
+        WindowState {
+            id,
+            width: 1000,
+            height: 800,
+            is_floating: true,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_save_and_restore_after_restart() {
+        let temp_dir = tempdir().unwrap();
+        let state = AppState {
+            windows: vec![tracked(2), tracked(1)],
+            maximized_window_id: Some(1),
+            is_flipped: true,
+            scroll_offset: 40,
+            ..Default::default()
+        };
+
+        let mut ctx = Ctx {
+            state,
+            config: mock_config(),
+            socket: MockNiri::new(vec![
+                app_window(1, "telegram", "Chats (3)", 1),
+                app_window(2, "firefox", "Docs", 1),
+            ]),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+        snapshot_save(&mut ctx, "chat").expect("save failed");
+
+        // Same apps with new ids after a restart, one of them elsewhere and with a new title
+        let mut ctx = Ctx {
+            state: AppState {
+                ignored_windows: vec![12],
+                ..Default::default()
+            },
+            config: mock_config(),
+            socket: MockNiri::new(vec![
+                app_window(11, "firefox", "Other", 1),
+                app_window(12, "telegram", "Chats (5)", 3),
+                app_window(13, "firefox", "Docs", 1),
+            ]),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+        snapshot_restore(&mut ctx, "chat").expect("restore failed");
+
+        let ids: Vec<_> = ctx.state.windows.iter().map(|w| w.id).collect();
+        assert_eq!(ids, vec![13, 12]);
+        assert_eq!(ctx.state.maximized_window_id, Some(12));
+        assert!(ctx.state.is_flipped);
+        assert!(!ctx.state.is_hidden);
+        assert!(ctx.state.ignored_windows.is_empty());
+        assert!(ctx.socket.sent_actions.iter().any(|a| matches!(
+            a,
+            Action::MoveWindowToWorkspace {
+                window_id: Some(12),
+                reference: WorkspaceReferenceArg::Id(1),
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn test_snapshot_restore_keeps_other_sidebar_windows_after_restored_ones() {
+        let temp_dir = tempdir().unwrap();
+        let mut snapshots = BTreeMap::new();
+        snapshots.insert(
+            "work".to_string(),
+            Snapshot {
+                windows: vec![Fingerprint {
+                    app_id: Some("firefox".into()),
+                    title: Some("Docs".into()),
+                }],
+                maximized: None,
+                is_hidden: true,
+                is_flipped: false,
+                scroll_offset: 0,
+            },
+        );
+        save_snapshots(&snapshots, temp_dir.path()).unwrap();
+
+        let state = AppState {
+            windows: vec![tracked(1), tracked(2)],
+            ..Default::default()
+        };
+        let mut ctx = Ctx {
+            state,
+            config: mock_config(),
+            socket: MockNiri::new(vec![
+                app_window(1, "telegram", "Chats", 1),
+                app_window(2, "firefox", "Docs", 1),
+            ]),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+        snapshot_restore(&mut ctx, "work").expect("restore failed");
+
+        let ids: Vec<_> = ctx.state.windows.iter().map(|w| w.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert!(ctx.state.is_hidden);
+        // Already in the sidebar, so it isn't toggled floating or resized by re-adding it
+        assert!(
+            !ctx.socket
+                .sent_actions
+                .iter()
+                .any(|a| matches!(a, Action::ToggleWindowFloating { .. }))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_restore_unknown_name_fails() {
+        let temp_dir = tempdir().unwrap();
+        let mut ctx = Ctx {
+            state: AppState::default(),
+            config: mock_config(),
+            socket: MockNiri::new(vec![]),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        assert!(snapshot_restore(&mut ctx, "nope").is_err());
+    }
+}
@@ -2,10 +2,10 @@ use crate::Ctx;
 use crate::commands::maximize::restore_sidebar_window_sizes;
 use crate::commands::reorder;
 use crate::niri::NiriClient;
-use crate::state::{WindowState, save_state};
+use crate::state::{TiledOrigin, WindowState, save_state};
 use crate::window_rules::resolve_window_size;
 use anyhow::{Context, Result};
-use niri_ipc::{Action, SizeChange, Window};
+use niri_ipc::{Action, SizeChange, Window, WorkspaceReferenceArg};
 
 pub fn toggle_window<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
     let focused = ctx.socket.get_active_window()?;
@@ -18,7 +18,7 @@ pub fn toggle_window<C: NiriClient>(ctx: &mut Ctx<C>) -> Result<()> {
         add_to_sidebar(ctx, &focused)?;
     }
 
-    save_state(&ctx.state, &ctx.cache_dir)?;
+    save_state(&ctx.state, &ctx.runtime_dir)?;
     reorder(ctx)?;
 
     Ok(())
@@ -34,6 +34,23 @@ pub fn add_to_sidebar<C: NiriClient>(ctx: &mut Ctx<C>, window: &Window) -> Resul
         position: window.layout.tile_pos_in_workspace_view,
     };
     ctx.state.windows.push(w_state);
+    if let Some((column, tile)) = window.layout.pos_in_scrolling_layout {
+        let shared_column = tile > 1
+            || ctx.socket.get_windows()?.iter().any(|w| {
+                w.id != window.id
+                    && w.workspace_id == window.workspace_id
+                    && w.layout.pos_in_scrolling_layout.map(|(c, _)| c) == Some(column)
+            });
+        ctx.state.tiled_origins.insert(
+            window.id,
+            TiledOrigin {
+                workspace_id: window.workspace_id,
+                column,
+                tile,
+                shared_column,
+            },
+        );
+    }
 
     // If maximize is currently active (or focus-maximize mode is enabled),
     // a newly focused sidebar window should become the maximized one.
@@ -49,11 +66,17 @@ pub fn add_to_sidebar<C: NiriClient>(ctx: &mut Ctx<C>, window: &Window) -> Resul
         });
     }
 
+    let screen = ctx.socket.get_screen()?;
     let (target_width, target_height) = resolve_window_size(
         &ctx.config.window_rule,
         window,
         ctx.config.geometry.width,
         ctx.config.geometry.height,
+        screen.size(),
+    );
+    let (target_width, target_height) = (
+        screen.snap_size(target_width),
+        screen.snap_size(target_height),
     );
 
     let _ = ctx.socket.send_action(Action::SetWindowWidth {
@@ -78,6 +101,13 @@ fn remove_from_sidebar<C: NiriClient>(ctx: &mut Ctx<C>, window: &Window) -> Resu
         .context("Window was not found in sidebar state")?;
 
     let w_state = ctx.state.windows.remove(index);
+    let origin = ctx.state.tiled_origins.remove(&w_state.id);
+    // Counted while the window still floats, tiling it again shifts the columns right of where
+    // niri puts it
+    let tiles_in_column = match &origin {
+        Some(origin) if !w_state.is_floating => tiles_in_column(ctx, window, origin)?,
+        _ => 0,
+    };
     ctx.state.ignored_windows.push(w_state.id);
     if ctx.state.maximized_window_id == Some(w_state.id) {
         ctx.state.maximized_window_id = None;
@@ -110,6 +140,87 @@ fn remove_from_sidebar<C: NiriClient>(ctx: &mut Ctx<C>, window: &Window) -> Resu
         });
     }
 
+    if !w_state.is_floating
+        && let Some(origin) = origin
+    {
+        restore_tiled_position(ctx, window, &origin, tiles_in_column)?;
+    }
+
+    Ok(())
+}
+
+/// The windows left in the column a sidebar window came from, 0 if it had the column to itself.
+fn tiles_in_column<C: NiriClient>(
+    ctx: &mut Ctx<C>,
+    window: &Window,
+    origin: &TiledOrigin,
+) -> Result<usize> {
+    if !origin.shared_column {
+        return Ok(0);
+    }
+    let workspace_id = origin.workspace_id.or(window.workspace_id);
+    Ok(ctx
+        .socket
+        .get_windows()?
+        .iter()
+        .filter(|w| {
+            w.id != window.id
+                && w.workspace_id == workspace_id
+                && w.layout.pos_in_scrolling_layout.map(|(c, _)| c) == Some(origin.column)
+        })
+        .count())
+}
+
+/// Puts a window that was tiled before joining the sidebar back on its workspace, column and tile.
+///
+/// niri only moves the focused column by index, so the window is focused for the moves and focus
+/// goes back afterwards. If the window shared its column, it is placed right of that column and
+/// consumed into it, which adds it at the bottom, then moved up to its old tile.
+fn restore_tiled_position<C: NiriClient>(
+    ctx: &mut Ctx<C>,
+    window: &Window,
+    origin: &TiledOrigin,
+    tiles_in_column: usize,
+) -> Result<()> {
+    let previous_focus = match window.is_focused {
+        true => None,
+        false => ctx.socket.get_active_window().ok().map(|w| w.id),
+    };
+
+    if let Some(workspace_id) = origin.workspace_id
+        && window.workspace_id != Some(workspace_id)
+    {
+        let _ = ctx.socket.send_action(Action::MoveWindowToWorkspace {
+            window_id: Some(window.id),
+            reference: WorkspaceReferenceArg::Id(workspace_id),
+            focus: window.is_focused,
+        });
+    }
+    if !window.is_focused {
+        let _ = ctx
+            .socket
+            .send_action(Action::FocusWindow { id: window.id });
+    }
+
+    if tiles_in_column == 0 {
+        let _ = ctx.socket.send_action(Action::MoveColumnToIndex {
+            index: origin.column,
+        });
+    } else {
+        let _ = ctx.socket.send_action(Action::MoveColumnToIndex {
+            index: origin.column + 1,
+        });
+        let _ = ctx.socket.send_action(Action::ConsumeOrExpelWindowLeft {
+            id: Some(window.id),
+        });
+        for _ in origin.tile..=tiles_in_column {
+            let _ = ctx.socket.send_action(Action::MoveWindowUp {});
+        }
+    }
+
+    if let Some(id) = previous_focus {
+        let _ = ctx.socket.send_action(Action::FocusWindow { id });
+    }
     Ok(())
 }
 
@@ -135,7 +246,8 @@ mod tests {
             state: AppState::default(),
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_window(&mut ctx).expect("Command failed");
@@ -180,7 +292,8 @@ mod tests {
             state: AppState::default(),
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_window(&mut ctx).expect("Command failed");
@@ -227,15 +340,16 @@ mod tests {
         use regex::Regex;
         config.window_rule = vec![WindowRule {
             app_id: Some(Regex::new("special").unwrap()),
-            width: Some(500),
-            height: Some(600),
+            width: Some(500.into()),
+            height: Some(600.into()),
             ..Default::default()
         }];
         let mut ctx = Ctx {
             state: AppState::default(),
             config,
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
         toggle_window(&mut ctx).expect("Command failed");
 
@@ -281,7 +395,8 @@ mod tests {
             state,
             config: Config::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_window(&mut ctx).expect("Command failed");
@@ -344,7 +459,8 @@ mod tests {
             state,
             config: Default::default(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_window(&mut ctx).expect("Command failed");
@@ -380,6 +496,177 @@ mod tests {
         )));
     }
 
+    #[test]
+    fn test_remove_from_sidebar_restores_workspace_and_column() {
+        let temp_dir = tempdir().unwrap();
+        let win = mock_window(100, true, true, 1, Some((1.0, 2.0)));
+        let mock = MockNiri::new(vec![win]);
+
+        let mut state = AppState::default();
+        state.windows.push(WindowState {
+            id: 100,
+            width: 1000,
+            height: 800,
+            is_floating: false,
+            position: None,
+        });
+        state.tiled_origins.insert(
+            100,
+            TiledOrigin {
+                workspace_id: Some(2),
+                column: 3,
+                tile: 1,
+                shared_column: false,
+            },
+        );
+
+        let mut ctx = Ctx {
+            state,
+            config: Default::default(),
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        toggle_window(&mut ctx).expect("Command failed");
+
+        let actions = &ctx.socket.sent_actions;
+        let tile = actions
+            .iter()
+            .position(|a| matches!(a, Action::ToggleWindowFloating { id: Some(100) }))
+            .expect("window should be tiled again");
+        let move_ws = actions
+            .iter()
+            .position(|a| {
+                matches!(
+                    a,
+                    Action::MoveWindowToWorkspace {
+                        window_id: Some(100),
+                        reference: WorkspaceReferenceArg::Id(2),
+                        focus: true,
+                    }
+                )
+            })
+            .expect("window should go back to its workspace");
+        let move_column = actions
+            .iter()
+            .position(|a| matches!(a, Action::MoveColumnToIndex { index: 3 }))
+            .expect("window should go back to its column");
+
+        assert!(tile < move_ws && move_ws < move_column);
+    }
+
+    #[test]
+    fn test_add_to_sidebar_records_tiled_position() {
+        let temp_dir = tempdir().unwrap();
+        let mut win = mock_window(100, true, false, 4, None);
+        win.layout.pos_in_scrolling_layout = Some((2, 1));
+        let mock = MockNiri::new(vec![win]);
+
+        let mut ctx = Ctx {
+            state: AppState::default(),
+            config: mock_config(),
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        toggle_window(&mut ctx).expect("Command failed");
+
+        assert_eq!(
+            ctx.state.tiled_origins.get(&100),
+            Some(&TiledOrigin {
+                workspace_id: Some(4),
+                column: 2,
+                tile: 1,
+                shared_column: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_remove_from_sidebar_restores_tile_in_shared_column() {
+        let temp_dir = tempdir().unwrap();
+        let win = mock_window(100, true, true, 1, Some((1.0, 2.0)));
+        let mut top = mock_window(200, false, false, 1, None);
+        top.layout.pos_in_scrolling_layout = Some((2, 1));
+        let mut bottom = mock_window(300, false, false, 1, None);
+        bottom.layout.pos_in_scrolling_layout = Some((2, 2));
+        let mock = MockNiri::new(vec![win, top, bottom]);
+
+        let mut state = AppState::default();
+        state.windows.push(WindowState {
+            id: 100,
+            width: 1000,
+            height: 800,
+            is_floating: false,
+            position: None,
+        });
+        state.tiled_origins.insert(
+            100,
+            TiledOrigin {
+                workspace_id: Some(1),
+                column: 2,
+                tile: 2,
+                shared_column: true,
+            },
+        );
+
+        let mut ctx = Ctx {
+            state,
+            config: Default::default(),
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+
+        toggle_window(&mut ctx).expect("Command failed");
+
+        let actions: Vec<_> = ctx
+            .socket
+            .sent_actions
+            .iter()
+            .skip_while(|a| !matches!(a, Action::MoveColumnToIndex { .. }))
+            .collect();
+        assert!(matches!(actions[0], Action::MoveColumnToIndex { index: 3 }));
+        assert!(matches!(
+            actions[1],
+            Action::ConsumeOrExpelWindowLeft { id: Some(100) }
+        ));
+        // Consumed at the bottom as third tile, one up is the second again
+        assert!(matches!(actions[2], Action::MoveWindowUp {}));
+        assert!(!matches!(actions.get(3), Some(Action::MoveWindowUp {})));
+    }
+
+    #[test]
+    fn test_restore_tiled_position_focuses_the_window_and_back() {
+        let temp_dir = tempdir().unwrap();
+        let win = mock_window(100, false, false, 1, None);
+        let focused = mock_window(200, true, false, 1, None);
+        let mock = MockNiri::new(vec![win.clone(), focused]);
+        let mut ctx = Ctx {
+            state: AppState::default(),
+            config: Default::default(),
+            socket: mock,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+        let origin = TiledOrigin {
+            workspace_id: Some(1),
+            column: 4,
+            tile: 1,
+            shared_column: false,
+        };
+
+        restore_tiled_position(&mut ctx, &win, &origin, 0).unwrap();
+
+        let actions = &ctx.socket.sent_actions;
+        assert_eq!(actions.len(), 3);
+        assert!(matches!(actions[0], Action::FocusWindow { id: 100 }));
+        assert!(matches!(actions[1], Action::MoveColumnToIndex { index: 4 }));
+        assert!(matches!(actions[2], Action::FocusWindow { id: 200 }));
+    }
+
     #[test]
     fn test_remove_maximized_window_restores_other_sidebar_sizes() {
         let temp_dir = tempdir().unwrap();
@@ -408,7 +695,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_window(&mut ctx).expect("Command failed");
@@ -445,7 +733,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_window(&mut ctx).expect("Command failed");
@@ -467,7 +756,8 @@ mod tests {
             state,
             config: mock_config(),
             socket: mock,
-            cache_dir: temp_dir.path().to_path_buf(),
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
         };
 
         toggle_window(&mut ctx).expect("Command failed");
@@ -1,8 +1,12 @@
 use anyhow::{Context, Result};
+use niri_ipc::Output;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 pub const DEFAULT_CONFIG_STR: &str = include_str!("../default_config.toml");
 
@@ -22,25 +26,266 @@ pub enum OverflowStrategy {
     Shrink,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub geometry: Geometry,
     pub margins: Margins,
     pub interaction: Interaction,
     #[serde(default)]
     pub window_rule: Vec<WindowRule>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub bar: Vec<Bar>,
+    #[serde(default)]
+    pub profile: Vec<Profile>,
+    #[serde(default)]
+    pub preset: BTreeMap<String, Preset>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A named overlay of the layout sections, switched at runtime with `preset set`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    pub geometry: Option<toml::Table>,
+    pub margins: Option<toml::Table>,
+    pub interaction: Option<toml::Table>,
+}
+
+/// Config overrides that apply while a set of outputs is connected, the first match wins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// Outputs that all have to be connected.
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    /// Bounds for the logical width of the widest connected output.
+    pub min_width: Option<i32>,
+    pub max_width: Option<i32>,
+    /// Any other config section, merged over the base config key by key.
+    #[serde(flatten)]
+    pub overrides: toml::Table,
+}
+
+impl Profile {
+    pub fn matches(&self, outputs: &[Output]) -> bool {
+        let enabled: Vec<_> = outputs.iter().filter(|o| o.logical.is_some()).collect();
+        let widest = enabled
+            .iter()
+            .filter_map(|o| o.logical.as_ref())
+            .map(|l| i32::try_from(l.width).unwrap_or(i32::MAX))
+            .max();
+
+        self.outputs
+            .iter()
+            .all(|name| enabled.iter().any(|o| &o.name == name))
+            && self
+                .min_width
+                .is_none_or(|min| widest.is_some_and(|w| w >= min))
+            && self
+                .max_width
+                .is_none_or(|max| widest.is_some_and(|w| w <= max))
+    }
+}
+
+impl Config {
+    /// The config for the connected outputs, and the name of the profile that applied if any.
+    pub fn for_outputs(&self, outputs: &[Output]) -> Result<(Config, Option<String>)> {
+        match self.profile.iter().find(|p| p.matches(outputs)) {
+            Some(profile) => {
+                let config = self
+                    .with_overrides(&profile.overrides)
+                    .with_context(|| format!("Invalid profile {:?}", profile.name))?;
+                Ok((config, Some(profile.name.clone())))
+            }
+            None => Ok((self.clone(), None)),
+        }
+    }
+
+    /// This config with a preset merged over it, or unchanged without one.
+    pub fn with_preset(&self, name: Option<&str>) -> Result<Config> {
+        let Some(name) = name else {
+            return Ok(self.clone());
+        };
+        let preset = self
+            .preset
+            .get(name)
+            .with_context(|| format!("No preset named {:?}", name))?;
+
+        let mut overrides = toml::Table::new();
+        let sections = [
+            ("geometry", &preset.geometry),
+            ("margins", &preset.margins),
+            ("interaction", &preset.interaction),
+        ];
+        for (section, table) in sections {
+            if let Some(table) = table {
+                overrides.insert(section.into(), table.clone().into());
+            }
+        }
+        self.with_overrides(&overrides)
+            .with_context(|| format!("Invalid preset {:?}", name))
+    }
+
+    /// This config with `overrides` merged over it, tables are merged and anything else replaced.
+    pub fn with_overrides(&self, overrides: &toml::Table) -> Result<Config> {
+        let mut merged = toml::Table::try_from(self)?;
+        merge_tables(&mut merged, overrides);
+        Ok(merged.try_into()?)
+    }
+}
+
+fn merge_tables(base: &mut toml::Table, overrides: &toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                merge_tables(base, overrides)
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Geometry {
-    pub width: i32,
-    pub height: i32,
+    pub width: Dimension,
+    pub height: Dimension,
     pub gap: i32,
     #[serde(default = "default_overflow")]
     pub overflow: OverflowStrategy,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A length in logical pixels, or relative to the output it ends up on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Size {
+    Pixels(i32),
+    Percent(f64),
+}
+
+impl Size {
+    pub fn resolve(&self, output_length: i32) -> i32 {
+        match self {
+            Size::Pixels(px) => *px,
+            Size::Percent(percent) => (f64::from(output_length) * percent / 100.0).round() as i32,
+        }
+    }
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_suffix('%') {
+            Some(percent) => percent
+                .trim()
+                .parse()
+                .map(Size::Percent)
+                .map_err(|_| format!("invalid percentage: {:?}", s)),
+            None => s
+                .parse()
+                .map(Size::Pixels)
+                .map_err(|_| format!("invalid size: {:?}", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Size {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawSize {
+            Pixels(i32),
+            Text(String),
+        }
+
+        match RawSize::deserialize(deserializer)? {
+            RawSize::Pixels(px) => Ok(Size::Pixels(px)),
+            RawSize::Text(text) => text.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for Size {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Size::Pixels(px) => serializer.serialize_i32(*px),
+            Size::Percent(percent) => serializer.serialize_str(&format!("{}%", percent)),
+        }
+    }
+}
+
+/// A window width or height, e.g. `400`, `"25%"` or `{ size = "25%", min = 300, max = 600 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawDimension")]
+pub struct Dimension {
+    pub size: Size,
+    pub min: Option<Size>,
+    pub max: Option<Size>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDimension {
+    Plain(Size),
+    Clamped {
+        size: Size,
+        min: Option<Size>,
+        max: Option<Size>,
+    },
+}
+
+impl From<RawDimension> for Dimension {
+    fn from(raw: RawDimension) -> Self {
+        match raw {
+            RawDimension::Plain(size) => size.into(),
+            RawDimension::Clamped { size, min, max } => Dimension { size, min, max },
+        }
+    }
+}
+
+impl From<Size> for Dimension {
+    fn from(size: Size) -> Self {
+        Dimension {
+            size,
+            min: None,
+            max: None,
+        }
+    }
+}
+
+impl From<i32> for Dimension {
+    fn from(px: i32) -> Self {
+        Size::Pixels(px).into()
+    }
+}
+
+impl Dimension {
+    /// Resolves against the matching side of the output, `min` wins if it conflicts with `max`.
+    pub fn resolve(&self, output_length: i32) -> i32 {
+        let mut value = self.size.resolve(output_length);
+        if let Some(max) = self.max {
+            value = value.min(max.resolve(output_length));
+        }
+        if let Some(min) = self.min {
+            value = value.max(min.resolve(output_length));
+        }
+        value.max(1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Margins {
     #[serde(default = "default_margin")]
     pub top: i32,
@@ -50,9 +295,11 @@ pub struct Margins {
     pub left: i32,
     #[serde(default = "default_margin")]
     pub bottom: i32,
+    #[serde(default)]
+    pub auto_margins: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Interaction {
     pub peek: i32,
     pub focus_peek: Option<i32>,
@@ -60,6 +307,13 @@ pub struct Interaction {
     pub position: SidebarPosition,
     #[serde(default = "default_sticky")]
     pub sticky: bool,
+    /// Only follow to workspaces on this output when sticky.
+    #[serde(default)]
+    pub sticky_output: Option<String>,
+    /// Opacity of unfocused sidebar windows, applied by the rules from `generate-niri-rules` to
+    /// windows matched by a window rule without an opacity of its own.
+    #[serde(default)]
+    pub opacity: Option<f64>,
 }
 
 impl Interaction {
@@ -84,18 +338,41 @@ fn default_margin() -> i32 {
     0
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct WindowRule {
     #[serde(default, with = "serde_regex")]
     pub app_id: Option<Regex>,
     #[serde(default, with = "serde_regex")]
     pub title: Option<Regex>,
-    pub width: Option<i32>,
-    pub height: Option<i32>,
+    pub width: Option<Dimension>,
+    pub height: Option<Dimension>,
     pub peek: Option<i32>,
     pub focus_peek: Option<i32>,
     #[serde(default)]
     pub auto_add: bool,
+    pub max_instances: Option<usize>,
+    pub opacity: Option<f64>,
+}
+
+/// A panel reserving space along an output edge, added to the margins when `auto_margins` is on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bar {
+    /// Layer-shell namespace of the bar, if set the bar only counts on outputs showing it.
+    pub namespace: Option<String>,
+    /// Output the bar is on, if not set it counts on every output.
+    pub output: Option<String>,
+    pub edge: Edge,
+    pub size: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Hooks {
+    pub on_add: Option<String>,
+    pub on_remove: Option<String>,
+    pub on_hide: Option<String>,
+    pub on_show: Option<String>,
+    pub on_maximize: Option<String>,
+    pub on_flip: Option<String>,
 }
 
 impl Default for Config {
@@ -110,38 +387,37 @@ pub fn get_config_dir() -> Result<PathBuf> {
     Ok(path)
 }
 
-pub fn load_config() -> Config {
-    let Ok(mut path) = get_config_dir() else {
-        return Config::default();
-    };
+pub fn get_config_path() -> Result<PathBuf> {
+    let mut path = get_config_dir()?;
     path.push("config.toml");
+    Ok(path)
+}
 
-    if path.exists() {
-        if let Ok(content) = fs::read_to_string(&path) {
-            match toml::from_str(&content) {
-                Ok(cfg) => return cfg,
-                Err(e) => eprintln!("Error parsing config.toml: {}. Using defaults.", e),
-            }
+pub fn load_config(path: &Path) -> Config {
+    if path.exists()
+        && let Ok(content) = fs::read_to_string(path)
+    {
+        match toml::from_str(&content) {
+            Ok(cfg) => return cfg,
+            Err(e) => crate::error!("Error parsing {:?}: {}. Using defaults.", path, e),
         }
     }
     Config::default()
 }
 
-pub fn init_config() -> Result<()> {
-    let mut path = get_config_dir()?;
-
-    if !path.exists() {
-        fs::create_dir_all(&path)?;
-        println!("Created directory: {:?}", path);
+pub fn init_config(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent()
+        && !dir.exists()
+    {
+        fs::create_dir_all(dir)?;
+        println!("Created directory: {:?}", dir);
     }
 
-    path.push("config.toml");
-
     if path.exists() {
         anyhow::bail!("Config file already exists at {:?}", path);
     }
 
-    fs::write(&path, DEFAULT_CONFIG_STR)?;
+    fs::write(path, DEFAULT_CONFIG_STR)?;
     println!("Default config written to {:?}", path);
     Ok(())
 }
@@ -149,6 +425,7 @@ pub fn init_config() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::mock_output;
 
     #[test]
     fn test_missing_overflow_defaults_to_scroll() {
@@ -169,4 +446,207 @@ mod tests {
 
         assert_eq!(config.geometry.overflow, OverflowStrategy::Scroll);
     }
+
+    #[test]
+    fn test_dimensions_accept_pixels_percentages_and_clamps() {
+        let config: Config = toml::from_str(
+            r#"
+            [geometry]
+            width = "25%"
+            height = { size = "50%", min = 300, max = "40%" }
+            gap = 10
+
+            [margins]
+
+            [interaction]
+            peek = 10
+
+            [[window_rule]]
+            width = 500
+            height = "  30 % "
+        "#,
+        )
+        .expect("config with relative sizes should parse");
+
+        assert_eq!(config.geometry.width.resolve(1920), 480);
+        // 50% of 1080 is clamped to 40% (432)
+        assert_eq!(config.geometry.height.resolve(1080), 432);
+        // 40% of 600 (240) is below the 300 minimum
+        assert_eq!(config.geometry.height.resolve(600), 300);
+        assert_eq!(config.window_rule[0].width, Some(Dimension::from(500)));
+        assert_eq!(config.window_rule[0].height.unwrap().resolve(1000), 300);
+    }
+
+    #[test]
+    fn test_invalid_dimension_is_rejected() {
+        let result: Result<Config, _> = toml::from_str(
+            r#"
+            [geometry]
+            width = "wide"
+            height = 335
+            gap = 10
+
+            [margins]
+
+            [interaction]
+            peek = 10
+        "#,
+        );
+
+        assert!(result.is_err());
+    }
+
+    fn profiles() -> Config {
+        toml::from_str(
+            r#"
+            [geometry]
+            width = 400
+            height = 335
+            gap = 10
+
+            [margins]
+            top = 50
+
+            [interaction]
+            peek = 10
+
+            [[window_rule]]
+            app_id = "firefox"
+
+            [[profile]]
+            name = "docked"
+            outputs = ["DP-1", "DP-2"]
+            geometry = { width = "20%" }
+            window_rule = []
+
+            [[profile]]
+            name = "laptop"
+            max_width = 1400
+
+            [profile.margins]
+            top = 0
+        "#,
+        )
+        .expect("config with profiles should parse")
+    }
+
+    #[test]
+    fn test_profile_selected_by_connected_outputs() {
+        let config = profiles();
+
+        let docked = [
+            mock_output("eDP-1", 1280, 720),
+            mock_output("DP-1", 3840, 2160),
+            mock_output("DP-2", 3840, 2160),
+        ];
+        let (docked_config, name) = config.for_outputs(&docked).unwrap();
+        assert_eq!(name.as_deref(), Some("docked"));
+        assert_eq!(docked_config.geometry.width.resolve(3840), 768);
+        // Untouched keys of an overridden section are kept
+        assert_eq!(docked_config.geometry.gap, 10);
+        assert!(docked_config.window_rule.is_empty());
+
+        let (laptop_config, name) = config
+            .for_outputs(&[mock_output("eDP-1", 1366, 768)])
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("laptop"));
+        assert_eq!(laptop_config.margins.top, 0);
+        assert_eq!(laptop_config.geometry.width.resolve(1366), 400);
+        assert_eq!(laptop_config.window_rule.len(), 1);
+
+        let (other_config, name) = config
+            .for_outputs(&[mock_output("HDMI-A-1", 2560, 1440)])
+            .unwrap();
+        assert_eq!(name, None);
+        assert_eq!(other_config.margins.top, 50);
+    }
+
+    #[test]
+    fn test_profile_ignores_disabled_outputs() {
+        let config = profiles();
+        let mut dp2 = mock_output("DP-2", 3840, 2160);
+        dp2.logical = None;
+
+        let (_, name) = config
+            .for_outputs(&[mock_output("DP-1", 3840, 2160), dp2])
+            .unwrap();
+        assert_eq!(name, None);
+    }
+
+    #[test]
+    fn test_invalid_profile_is_an_error() {
+        let mut config = profiles();
+        config.profile[1]
+            .overrides
+            .insert("geometry".into(), toml::Value::String("wide".into()));
+
+        assert!(
+            config
+                .for_outputs(&[mock_output("eDP-1", 1366, 768)])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_preset_overlays_layout_sections() {
+        let config: Config = toml::from_str(
+            r#"
+            [geometry]
+            width = 400
+            height = 335
+            gap = 10
+
+            [margins]
+            top = 50
+
+            [interaction]
+            peek = 10
+
+            [preset.glance]
+            geometry = { width = 250 }
+
+            [preset.working.geometry]
+            width = "40%"
+            height = 600
+
+            [preset.working.interaction]
+            peek = 30
+        "#,
+        )
+        .expect("config with presets should parse");
+
+        let glance = config.with_preset(Some("glance")).unwrap();
+        assert_eq!(glance.geometry.width.resolve(1920), 250);
+        assert_eq!(glance.geometry.height.resolve(1080), 335);
+
+        let working = config.with_preset(Some("working")).unwrap();
+        assert_eq!(working.geometry.width.resolve(1920), 768);
+        assert_eq!(working.interaction.peek, 30);
+        assert_eq!(working.margins.top, 50);
+
+        assert_eq!(config.with_preset(None).unwrap().interaction.peek, 10);
+        assert!(config.with_preset(Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_preset_only_accepts_layout_sections() {
+        let result: Result<Config, _> = toml::from_str(
+            r#"
+            [geometry]
+            width = 400
+            height = 335
+            gap = 10
+
+            [margins]
+
+            [interaction]
+            peek = 10
+
+            [preset.broken.hooks]
+            on_add = "true"
+        "#,
+        );
+
+        assert!(result.is_err());
+    }
 }
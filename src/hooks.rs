@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+
+use niri_ipc::Window;
+
+use crate::AppState;
+use crate::config::Hooks;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    Added(u64),
+    Removed(u64),
+    Hidden,
+    Shown,
+    Maximized(u64),
+    Flipped,
+}
+
+impl HookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::Added(_) => "added",
+            HookEvent::Removed(_) => "removed",
+            HookEvent::Hidden => "hidden",
+            HookEvent::Shown => "shown",
+            HookEvent::Maximized(_) => "maximized",
+            HookEvent::Flipped => "flipped",
+        }
+    }
+
+    fn window_id(&self) -> Option<u64> {
+        match self {
+            HookEvent::Added(id) | HookEvent::Removed(id) | HookEvent::Maximized(id) => Some(*id),
+            HookEvent::Hidden | HookEvent::Shown | HookEvent::Flipped => None,
+        }
+    }
+
+    fn command<'a>(&self, hooks: &'a Hooks) -> Option<&'a str> {
+        match self {
+            HookEvent::Added(_) => hooks.on_add.as_deref(),
+            HookEvent::Removed(_) => hooks.on_remove.as_deref(),
+            HookEvent::Hidden => hooks.on_hide.as_deref(),
+            HookEvent::Shown => hooks.on_show.as_deref(),
+            HookEvent::Maximized(_) => hooks.on_maximize.as_deref(),
+            HookEvent::Flipped => hooks.on_flip.as_deref(),
+        }
+    }
+}
+
+/// Compares two snapshots of the sidebar state and returns the events that lead from one to the other.
+pub fn diff_state(old: &AppState, new: &AppState) -> Vec<HookEvent> {
+    let mut events = Vec::new();
+
+    for w in &new.windows {
+        if !old.windows.iter().any(|o| o.id == w.id) {
+            events.push(HookEvent::Added(w.id));
+        }
+    }
+    for w in &old.windows {
+        if !new.windows.iter().any(|n| n.id == w.id) {
+            events.push(HookEvent::Removed(w.id));
+        }
+    }
+
+    if old.is_hidden != new.is_hidden {
+        events.push(if new.is_hidden {
+            HookEvent::Hidden
+        } else {
+            HookEvent::Shown
+        });
+    }
+
+    if let Some(id) = new.maximized_window_id
+        && old.maximized_window_id != Some(id)
+    {
+        events.push(HookEvent::Maximized(id));
+    }
+
+    if old.is_flipped != new.is_flipped {
+        events.push(HookEvent::Flipped);
+    }
+
+    events
+}
+
+pub fn hook_command(
+    hooks: &Hooks,
+    event: HookEvent,
+    windows: &HashMap<u64, Window>,
+) -> Option<Command> {
+    let script = event.command(hooks)?;
+
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(script)
+        .env("NIRI_SIDEBAR_EVENT", event.name())
+        .stdin(Stdio::null());
+
+    if let Some(id) = event.window_id() {
+        command.env("NIRI_SIDEBAR_WINDOW_ID", id.to_string());
+        let window = windows.get(&id);
+        command.env(
+            "NIRI_SIDEBAR_APP_ID",
+            window.and_then(|w| w.app_id.as_deref()).unwrap_or_default(),
+        );
+        command.env(
+            "NIRI_SIDEBAR_TITLE",
+            window.and_then(|w| w.title.as_deref()).unwrap_or_default(),
+        );
+    }
+
+    Some(command)
+}
+
+/// Spawns the configured hook for every event without waiting for it to finish.
+pub fn run_hooks(hooks: &Hooks, events: &[HookEvent], windows: &HashMap<u64, Window>) {
+    for event in events {
+        let Some(mut command) = hook_command(hooks, *event, windows) else {
+            continue;
+        };
+
+        match command.spawn() {
+            // Reap the child in the background so slow hooks don't stall the daemon
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => crate::error!("Failed to run {} hook: {}", event.name(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::WindowState;
+    use crate::test_utils::mock_window;
+    use std::ffi::OsStr;
+
+    fn window_state(id: u64) -> WindowState {
+        WindowState {
+            id,
+            width: 100,
+            height: 100,
+            is_floating: false,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_state_detects_added_and_removed() {
+        let old = AppState {
+            windows: vec![window_state(1), window_state(2)],
+            ..Default::default()
+        };
+        let new = AppState {
+            windows: vec![window_state(2), window_state(3)],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            diff_state(&old, &new),
+            vec![HookEvent::Added(3), HookEvent::Removed(1)]
+        );
+    }
+
+    #[test]
+    fn test_diff_state_detects_flags() {
+        let old = AppState {
+            is_hidden: true,
+            maximized_window_id: Some(1),
+            ..Default::default()
+        };
+        let new = AppState {
+            is_flipped: true,
+            maximized_window_id: Some(2),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            diff_state(&old, &new),
+            vec![
+                HookEvent::Shown,
+                HookEvent::Maximized(2),
+                HookEvent::Flipped
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_state_unchanged_is_empty() {
+        let state = AppState {
+            windows: vec![window_state(1)],
+            is_hidden: true,
+            ..Default::default()
+        };
+        let same = AppState {
+            windows: vec![window_state(1)],
+            is_hidden: true,
+            ..Default::default()
+        };
+
+        assert!(diff_state(&state, &same).is_empty());
+    }
+
+    #[test]
+    fn test_hook_command_passes_window_env() {
+        let hooks = Hooks {
+            on_add: Some("true".into()),
+            ..Default::default()
+        };
+        let windows = HashMap::from([(7, mock_window(7, false, false, 1, None))]);
+
+        let command = hook_command(&hooks, HookEvent::Added(7), &windows).unwrap();
+        let envs: HashMap<_, _> = command.get_envs().collect();
+
+        assert_eq!(
+            envs[OsStr::new("NIRI_SIDEBAR_EVENT")],
+            Some(OsStr::new("added"))
+        );
+        assert_eq!(
+            envs[OsStr::new("NIRI_SIDEBAR_WINDOW_ID")],
+            Some(OsStr::new("7"))
+        );
+        assert_eq!(
+            envs[OsStr::new("NIRI_SIDEBAR_APP_ID")],
+            Some(OsStr::new("test"))
+        );
+        assert_eq!(
+            envs[OsStr::new("NIRI_SIDEBAR_TITLE")],
+            Some(OsStr::new("Test Window"))
+        );
+    }
+
+    #[test]
+    fn test_hook_command_skips_unconfigured_event() {
+        let hooks = Hooks {
+            on_add: Some("true".into()),
+            ..Default::default()
+        };
+
+        assert!(hook_command(&hooks, HookEvent::Hidden, &HashMap::new()).is_none());
+    }
+}
@@ -0,0 +1,374 @@
+//! niri config snippets generated from our config, for things niri only supports in its own config.
+
+use crate::config::{Config, SidebarPosition, Size, WindowRule};
+use anyhow::{Result, bail};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const HEADER: &str = "// Generated by niri-sidebar, regenerate after changing its config.toml\n";
+
+/// Subcommands worth a key, with the keys the README suggests.
+const BINDS: &[(&str, &str, &str)] = &[
+    (
+        "Mod+S",
+        "toggle-window",
+        "Toggle the focused window into/out of the sidebar",
+    ),
+    (
+        "Mod+Shift+S",
+        "toggle-visibility",
+        "Toggle sidebar visibility (hide/show)",
+    ),
+    ("Mod+Ctrl+S", "flip", "Flip the order of the sidebar"),
+    ("Mod+Alt+J", "focus next", "Focus the next sidebar window"),
+    (
+        "Mod+Alt+K",
+        "focus prev",
+        "Focus the previous sidebar window",
+    ),
+    (
+        "Mod+Alt+Q",
+        "close",
+        "Close the focused window and close the gap",
+    ),
+    (
+        "Mod+Ctrl+M",
+        "maximize",
+        "Toggle maximize mode for the focused sidebar window",
+    ),
+    (
+        "Mod+Ctrl+Shift+M",
+        "toggle-maximize-focus-mode",
+        "Toggle auto-maximize mode for focused sidebar windows",
+    ),
+    ("Mod+Alt+R", "reorder", "Force reorder"),
+];
+
+/// Everything niri needs to run the sidebar: binds, the daemon and the window rules.
+pub fn generate_kdl(config: &Config, binary: &str) -> Result<String> {
+    let mut out = String::from(HEADER);
+    let binary = quoted_string(binary);
+
+    writeln!(out, "\nbinds {{")?;
+    for (key, command, description) in BINDS {
+        let args: Vec<_> = command.split(' ').map(quoted_string).collect();
+        writeln!(out, "    // {}", description)?;
+        writeln!(
+            out,
+            "    {} {{ spawn {} {}; }}",
+            key,
+            binary,
+            args.join(" ")
+        )?;
+    }
+    writeln!(out, "}}")?;
+
+    writeln!(out, "\nspawn-at-startup {} \"listen\"", binary)?;
+
+    // Apps enforcing a minimum size larger than the sidebar would overlap otherwise
+    writeln!(out, "\nwindow-rule {{")?;
+    writeln!(out, "    match is-floating=true")?;
+    writeln!(out, "    min-width 100")?;
+    writeln!(out, "    min-height 100")?;
+    writeln!(out, "}}")?;
+    for rule in &config.window_rule {
+        write_sidebar_rule(&mut out, rule, config.interaction.position)?;
+    }
+
+    write_opacity_rules(&mut out, config)?;
+    Ok(out)
+}
+
+/// Window rules dimming sidebar windows while they aren't focused, e.g. while peeking.
+///
+/// niri can't tell which windows are in the sidebar, so every rule is scoped to the app id and
+/// title of a `[[window_rule]]`, with the global opacity for rules without their own. Windows no
+/// rule matches can't be targeted and stay opaque, and since niri doesn't know whether the sidebar
+/// is hidden either, matched windows are dimmed whenever they float unfocused.
+pub fn niri_rules(config: &Config) -> Result<String> {
+    let mut out = String::from(HEADER);
+    write_opacity_rules(&mut out, config)?;
+    Ok(out)
+}
+
+fn write_opacity_rules(out: &mut String, config: &Config) -> Result<()> {
+    if let Some(opacity) = config.interaction.opacity {
+        check_opacity(opacity)?;
+    }
+    for rule in config.window_rule.iter().filter(|rule| has_matcher(rule)) {
+        if let Some(opacity) = rule.opacity.or(config.interaction.opacity) {
+            check_opacity(opacity)?;
+            writeln!(out, "\nwindow-rule {{")?;
+            writeln!(
+                out,
+                "    {} is-floating=true is-focused=false",
+                matcher(rule)
+            )?;
+            writeln!(out, "    opacity {}", opacity)?;
+            writeln!(out, "}}")?;
+        }
+    }
+    Ok(())
+}
+
+fn check_opacity(opacity: f64) -> Result<()> {
+    if !(0.0..=1.0).contains(&opacity) {
+        bail!("Opacity must be between 0 and 1, got {}", opacity);
+    }
+    Ok(())
+}
+
+/// Auto-added windows open floating right away, and a fixed size across the stack is allowed as
+/// a minimum. Along the stack the layout shrinks windows to fit (`overflow = "shrink"`, maximize),
+/// which a minimum there would make niri refuse.
+fn write_sidebar_rule(
+    out: &mut String,
+    rule: &WindowRule,
+    position: SidebarPosition,
+) -> Result<()> {
+    let min_size = match position {
+        SidebarPosition::Left | SidebarPosition::Right => rule
+            .width
+            .and_then(|d| fixed_pixels(d.size))
+            .map(|px| ("min-width", px)),
+        SidebarPosition::Top | SidebarPosition::Bottom => rule
+            .height
+            .and_then(|d| fixed_pixels(d.size))
+            .map(|px| ("min-height", px)),
+    };
+    if !has_matcher(rule) || (!rule.auto_add && min_size.is_none()) {
+        return Ok(());
+    }
+
+    writeln!(out, "\nwindow-rule {{")?;
+    writeln!(out, "    {}", matcher(rule))?;
+    if rule.auto_add {
+        writeln!(out, "    open-floating true")?;
+    }
+    if let Some((property, px)) = min_size {
+        writeln!(out, "    {} {}", property, px)?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn fixed_pixels(size: Size) -> Option<i32> {
+    match size {
+        Size::Pixels(px) => Some(px),
+        Size::Percent(_) => None,
+    }
+}
+
+fn has_matcher(rule: &WindowRule) -> bool {
+    rule.app_id.is_some() || rule.title.is_some()
+}
+
+fn matcher(rule: &WindowRule) -> String {
+    let mut matcher = String::from("match");
+    if let Some(app_id) = &rule.app_id {
+        matcher.push_str(&format!(" app-id={}", raw_string(app_id.as_str())));
+    }
+    if let Some(title) = &rule.title {
+        matcher.push_str(&format!(" title={}", raw_string(title.as_str())));
+    }
+    matcher
+}
+
+fn quoted_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A KDL raw string, so regexes can be copied over without escaping.
+fn raw_string(s: &str) -> String {
+    let mut hashes = String::from("#");
+    while s.contains(&format!("\"{}", hashes)) {
+        hashes.push('#');
+    }
+    format!("r{hashes}\"{s}\"{hashes}")
+}
+
+/// Writes a generated snippet to `output`, or prints it if no path is given.
+pub fn write_or_print(snippet: &str, output: Option<&Path>) -> Result<()> {
+    match output {
+        Some(path) => {
+            fs::write(path, snippet)?;
+            println!("niri config written to {:?}", path);
+        }
+        None => print!("{}", snippet),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppState;
+    use crate::config::OverflowStrategy;
+    use crate::layout::{LayoutInput, Screen, plan_layout};
+    use crate::state::WindowState;
+    use crate::test_utils::{mock_config, mock_window};
+    use regex::Regex;
+
+    #[test]
+    fn test_niri_rules_without_opacity_is_only_the_header() {
+        let config = mock_config();
+
+        assert_eq!(niri_rules(&config).unwrap(), HEADER);
+    }
+
+    #[test]
+    fn test_niri_rules_dim_unfocused_windows_matched_by_rules() {
+        let mut config = mock_config();
+        config.interaction.opacity = Some(0.8);
+        config.window_rule = vec![
+            WindowRule {
+                app_id: Some(Regex::new(r"^org\.telegram\.desktop$").unwrap()),
+                title: Some(Regex::new(r##"issue "#1""##).unwrap()),
+                opacity: Some(0.5),
+                ..Default::default()
+            },
+            WindowRule {
+                app_id: Some(Regex::new("firefox").unwrap()),
+                ..Default::default()
+            },
+            // Would match every window, which niri can't narrow down to the sidebar
+            WindowRule {
+                opacity: Some(0.3),
+                ..Default::default()
+            },
+        ];
+
+        let rules = niri_rules(&config).unwrap();
+
+        assert_eq!(
+            rules,
+            format!(
+                "{}{}",
+                HEADER,
+                r####"
+window-rule {
+    match app-id=r#"^org\.telegram\.desktop$"# title=r##"issue "#1""## is-floating=true is-focused=false
+    opacity 0.5
+}
+
+window-rule {
+    match app-id=r#"firefox"# is-floating=true is-focused=false
+    opacity 0.8
+}
+"####
+            )
+        );
+    }
+
+    #[test]
+    fn test_generate_kdl() {
+        let mut config = mock_config();
+        config.window_rule = vec![
+            WindowRule {
+                app_id: Some(Regex::new("^telegram$").unwrap()),
+                width: Some(500.into()),
+                auto_add: true,
+                opacity: Some(0.5),
+                ..Default::default()
+            },
+            WindowRule {
+                title: Some(Regex::new("Picture-in-Picture").unwrap()),
+                width: Some(Size::Percent(20.0).into()),
+                ..Default::default()
+            },
+        ];
+
+        let kdl = generate_kdl(&config, "/home/me/bin/niri \"sidebar\"").unwrap();
+
+        assert!(
+            kdl.contains(r#"    Mod+S { spawn "/home/me/bin/niri \"sidebar\"" "toggle-window"; }"#)
+        );
+        assert!(kdl.contains(
+            r#"    Mod+Alt+J { spawn "/home/me/bin/niri \"sidebar\"" "focus" "next"; }"#
+        ));
+        assert!(kdl.contains(r#"spawn-at-startup "/home/me/bin/niri \"sidebar\"" "listen""#));
+        assert!(kdl.contains(
+            r##"
+window-rule {
+    match app-id=r#"^telegram$"#
+    open-floating true
+    min-width 500
+}
+"##
+        ));
+        // Nothing to derive from a relative size on a rule that isn't auto-added
+        assert!(!kdl.contains("Picture-in-Picture"));
+        assert!(kdl.ends_with("    opacity 0.5\n}\n"));
+    }
+
+    #[test]
+    fn test_generate_kdl_leaves_the_stack_axis_to_the_layout() {
+        let mut config = mock_config();
+        config.geometry.overflow = OverflowStrategy::Shrink;
+        config.window_rule = vec![WindowRule {
+            app_id: Some(Regex::new("^telegram$").unwrap()),
+            width: Some(400.into()),
+            height: Some(500.into()),
+            auto_add: true,
+            ..Default::default()
+        }];
+        let windows: Vec<_> = (1..=3)
+            .map(|id| {
+                let mut window = mock_window(id, id == 1, true, 1, None);
+                window.app_id = Some("telegram".into());
+                window
+            })
+            .collect();
+        let state = AppState {
+            windows: (1..=3)
+                .map(|id| WindowState {
+                    id,
+                    width: 400,
+                    height: 500,
+                    is_floating: true,
+                    position: None,
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let kdl = generate_kdl(&config, "niri-sidebar").unwrap();
+        let plan = plan_layout(&LayoutInput {
+            windows: &windows,
+            workspace_id: 1,
+            screen: Screen {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+                scale: 1.0,
+            },
+            margins: config.margins,
+            config: &config,
+            state: &state,
+        });
+
+        // Three 500px windows don't fit, so they are shrunk below the rule's height
+        assert!(plan.windows.iter().all(|w| w.height < 500));
+        assert!(!kdl.contains("min-height 500"));
+        assert!(kdl.contains(
+            r##"
+window-rule {
+    match app-id=r#"^telegram$"#
+    open-floating true
+    min-width 400
+}
+"##
+        ));
+        assert!(plan.windows.iter().all(|w| w.width >= 400));
+    }
+
+    #[test]
+    fn test_niri_rules_reject_out_of_range_opacity() {
+        let mut config = mock_config();
+        config.interaction.opacity = Some(1.5);
+
+        assert!(niri_rules(&config).is_err());
+    }
+}
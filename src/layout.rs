@@ -0,0 +1,1144 @@
+use crate::config::{Edge, Margins, OverflowStrategy, SidebarPosition};
+use crate::window_rules::{resolve_rule_focus_peek, resolve_rule_peek, resolve_window_size};
+use crate::{AppState, Config};
+use niri_ipc::{Action, LayerSurface, PositionChange, SizeChange, Window};
+use serde::{Deserialize, Serialize};
+
+/// Everything the layout depends on, as plain data.
+#[derive(Debug, Clone, Copy)]
+pub struct LayoutInput<'a> {
+    /// All windows niri knows about, the sidebar ones are picked out using `state`.
+    pub windows: &'a [Window],
+    /// Workspace the sidebar is laid out on.
+    pub workspace_id: u64,
+    /// Output showing that workspace.
+    pub screen: Screen,
+    /// Space to keep free around the sidebar, see [`resolve_margins`].
+    pub margins: Margins,
+    pub config: &'a Config,
+    pub state: &'a AppState,
+}
+
+/// Logical rectangle and scale of an output, as niri reports it.
+///
+/// niri applies the output transform before reporting the logical size, so a rotated portrait
+/// monitor simply has a height larger than its width. Floating windows are positioned relative to
+/// their output, so the origin is carried along for tooling but never added to window positions.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Screen {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub scale: f64,
+}
+
+impl Screen {
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Rounds a logical coordinate to the closest physical pixel, so fractionally scaled outputs
+    /// don't end up with windows straddling two pixels.
+    pub fn snap(&self, logical: i32) -> f64 {
+        if !self.scale.is_finite() || self.scale <= 0.0 {
+            return f64::from(logical);
+        }
+        (f64::from(logical) * self.scale).round() / self.scale
+    }
+
+    /// Shrinks a logical size by a few pixels at most, until it covers whole physical pixels.
+    ///
+    /// Sizes are set in whole logical pixels, so at a fractional scale only some of them line up
+    /// with the pixel grid, e.g. multiples of 4 at 1.25. Sizes that can't be lined up that close
+    /// are kept as they are.
+    pub fn snap_size(&self, logical: i32) -> i32 {
+        if !self.scale.is_finite() || self.scale <= 0.0 {
+            return logical;
+        }
+        (logical.saturating_sub(MAX_SIZE_SNAP).max(1)..=logical)
+            .rev()
+            .find(|size| {
+                let physical = f64::from(*size) * self.scale;
+                (physical - physical.round()).abs() < 1e-6
+            })
+            .unwrap_or(logical)
+    }
+}
+
+// Enough for the usual fractional scales, 1.2 needs multiples of 5 and 1.125 multiples of 8
+const MAX_SIZE_SNAP: i32 = 7;
+
+/// Returns the configured margins, grown by the `[[bar]]`s on `output` if `auto_margins` is on.
+///
+/// niri doesn't report layer-shell exclusive zones over IPC, so bar sizes come from the config.
+/// `layers` is used to tell which output a bar with a namespace is currently shown on.
+pub fn resolve_margins(config: &Config, output: Option<&str>, layers: &[LayerSurface]) -> Margins {
+    let mut margins = config.margins;
+    if !margins.auto_margins {
+        return margins;
+    }
+
+    for bar in &config.bar {
+        if let Some(bar_output) = &bar.output
+            && Some(bar_output.as_str()) != output
+        {
+            continue;
+        }
+        if let Some(namespace) = &bar.namespace
+            && !layers
+                .iter()
+                .any(|l| &l.namespace == namespace && Some(l.output.as_str()) == output)
+        {
+            continue;
+        }
+
+        match bar.edge {
+            Edge::Top => margins.top += bar.size,
+            Edge::Right => margins.right += bar.size,
+            Edge::Bottom => margins.bottom += bar.size,
+            Edge::Left => margins.left += bar.size,
+        }
+    }
+
+    margins
+}
+
+/// Whether [`resolve_margins`] needs the layer surfaces to do its job.
+pub fn needs_layers(config: &Config) -> bool {
+    config.margins.auto_margins && config.bar.iter().any(|bar| bar.namespace.is_some())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WindowTarget {
+    width: i32,
+    height: i32,
+}
+
+/// Where a single sidebar window ends up after a reorder.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannedWindow {
+    pub id: u64,
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+    pub is_focused: bool,
+    pub x: f64,
+    pub y: f64,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Everything a reorder would do, without having done any of it.
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutPlan {
+    pub screen: Screen,
+    pub workspace_id: u64,
+    pub scroll_offset: i32,
+    pub windows: Vec<PlannedWindow>,
+    pub actions: Vec<Action>,
+}
+
+fn resolve_dimensions(window: &Window, config: &Config, display: (i32, i32)) -> WindowTarget {
+    let (width, height) = resolve_window_size(
+        &config.window_rule,
+        window,
+        config.geometry.width,
+        config.geometry.height,
+        display,
+    );
+
+    WindowTarget { width, height }
+}
+
+fn calculate_coordinates(
+    pos: SidebarPosition,
+    dims: WindowTarget,
+    screen: (i32, i32),
+    stack_offset: i32,
+    active_peek: i32,
+    state: &AppState,
+    margins: &Margins,
+) -> (i32, i32) {
+    let (sw, sh) = screen;
+    let (w, h) = (dims.width, dims.height);
+
+    match pos {
+        SidebarPosition::Right => {
+            let visible_x = sw - w - margins.right;
+            let hidden_x = sw - active_peek;
+            let x = if state.is_hidden { hidden_x } else { visible_x };
+
+            let start_y = sh - h - margins.bottom;
+            let y = start_y - stack_offset;
+            (x, y)
+        }
+        SidebarPosition::Left => {
+            let visible_x = margins.left;
+            let hidden_x = -w + active_peek;
+            let x = if state.is_hidden { hidden_x } else { visible_x };
+
+            let start_y = sh - h - margins.bottom;
+            let y = start_y - stack_offset;
+            (x, y)
+        }
+        SidebarPosition::Bottom => {
+            let start_x = margins.left;
+            let x = start_x + stack_offset;
+
+            let visible_y = sh - h - margins.bottom;
+            let hidden_y = sh - active_peek;
+            let y = if state.is_hidden { hidden_y } else { visible_y };
+            (x, y)
+        }
+        SidebarPosition::Top => {
+            let start_x = margins.left;
+            let x = start_x + stack_offset;
+
+            let visible_y = margins.top;
+            let hidden_y = -h + active_peek;
+            let y = if state.is_hidden { hidden_y } else { visible_y };
+            (x, y)
+        }
+    }
+}
+
+fn apply_maximize_sizes(
+    position: SidebarPosition,
+    dims: &mut [WindowTarget],
+    ordered_ids: &[u64],
+    maximized_window_id: Option<u64>,
+    display: (i32, i32),
+    margins: &Margins,
+    gap: i32,
+) {
+    let Some(maximized_id) = maximized_window_id else {
+        return;
+    };
+
+    let Some(max_idx) = ordered_ids.iter().position(|id| *id == maximized_id) else {
+        return;
+    };
+
+    let count = dims.len();
+    if count == 0 {
+        return;
+    }
+
+    let gaps_total = gap * (count.saturating_sub(1) as i32);
+
+    match position {
+        SidebarPosition::Left | SidebarPosition::Right => {
+            let available =
+                (display.1 - margins.top - margins.bottom - gaps_total).max(count as i32);
+            if count == 1 {
+                dims[0].height = available;
+                return;
+            }
+
+            let max_h = (available * 70) / 100;
+            let mut other_h = ((available - max_h) / ((count - 1) as i32)).max(60);
+            // The minimum can't take space from the maximized window, it gets at least as much
+            if other_h * (count as i32) > available {
+                other_h = (available / (count as i32)).max(1);
+            }
+            let max_h = available - other_h * ((count - 1) as i32);
+
+            for (idx, dim) in dims.iter_mut().enumerate() {
+                dim.height = if idx == max_idx { max_h } else { other_h };
+            }
+        }
+        SidebarPosition::Top | SidebarPosition::Bottom => {
+            let available =
+                (display.0 - margins.left - margins.right - gaps_total).max(count as i32);
+            if count == 1 {
+                dims[0].width = available;
+                return;
+            }
+
+            let max_w = (available * 70) / 100;
+            let mut other_w = ((available - max_w) / ((count - 1) as i32)).max(60);
+            // The minimum can't take space from the maximized window, it gets at least as much
+            if other_w * (count as i32) > available {
+                other_w = (available / (count as i32)).max(1);
+            }
+            let max_w = available - other_w * ((count - 1) as i32);
+
+            for (idx, dim) in dims.iter_mut().enumerate() {
+                dim.width = if idx == max_idx { max_w } else { other_w };
+            }
+        }
+    }
+}
+
+fn is_vertical(position: SidebarPosition) -> bool {
+    matches!(position, SidebarPosition::Left | SidebarPosition::Right)
+}
+
+fn stack_axis_size(position: SidebarPosition, dims: WindowTarget) -> i32 {
+    if is_vertical(position) {
+        dims.height
+    } else {
+        dims.width
+    }
+}
+
+fn viewport_size(position: SidebarPosition, display: (i32, i32), margins: &Margins) -> i32 {
+    if is_vertical(position) {
+        display.1 - margins.top - margins.bottom
+    } else {
+        display.0 - margins.left - margins.right
+    }
+    .max(0)
+}
+
+fn stack_length(position: SidebarPosition, dims: &[WindowTarget], gap: i32) -> i32 {
+    if dims.is_empty() {
+        return 0;
+    }
+
+    let windows_total: i32 = dims.iter().map(|dim| stack_axis_size(position, *dim)).sum();
+    windows_total + gap * (dims.len().saturating_sub(1) as i32)
+}
+
+fn stack_offset_before(
+    position: SidebarPosition,
+    dims: &[WindowTarget],
+    gap: i32,
+    index: usize,
+) -> i32 {
+    dims.iter()
+        .take(index)
+        .map(|dim| stack_axis_size(position, *dim) + gap)
+        .sum()
+}
+
+fn set_stack_axis_size(position: SidebarPosition, dims: &mut WindowTarget, size: i32) {
+    if is_vertical(position) {
+        dims.height = size;
+    } else {
+        dims.width = size;
+    }
+}
+
+fn shrink_to_viewport(
+    position: SidebarPosition,
+    dims: &mut [WindowTarget],
+    viewport: i32,
+    gap: i32,
+) {
+    if dims.is_empty() {
+        return;
+    }
+
+    let gaps_total = gap * (dims.len().saturating_sub(1) as i32);
+    let available = viewport - gaps_total;
+    let current_total: i32 = dims.iter().map(|dim| stack_axis_size(position, *dim)).sum();
+
+    if available <= 0 || current_total <= available {
+        return;
+    }
+
+    if available < dims.len() as i32 {
+        for dim in dims {
+            set_stack_axis_size(position, dim, 1);
+        }
+        return;
+    }
+
+    let mut sizes: Vec<i32> = dims
+        .iter()
+        .map(|dim| ((stack_axis_size(position, *dim) * available) / current_total).max(1))
+        .collect();
+
+    while sizes.iter().sum::<i32>() > available {
+        if let Some(size) = sizes.iter_mut().rev().find(|size| **size > 1) {
+            *size -= 1;
+        } else {
+            break;
+        }
+    }
+
+    let mut total = sizes.iter().sum::<i32>();
+    while total < available {
+        for size in sizes.iter_mut() {
+            if total >= available {
+                break;
+            }
+            *size += 1;
+            total += 1;
+        }
+    }
+
+    for (dim, size) in dims.iter_mut().zip(sizes) {
+        set_stack_axis_size(position, dim, size);
+    }
+}
+
+fn resolve_scroll_offset(
+    state: &AppState,
+    position: SidebarPosition,
+    dims: &[WindowTarget],
+    sidebar_windows: &[&Window],
+    viewport: i32,
+    gap: i32,
+) -> i32 {
+    let max_scroll = (stack_length(position, dims, gap) - viewport).max(0);
+    if max_scroll == 0 {
+        return 0;
+    }
+
+    let mut scroll_offset = state.scroll_offset.clamp(0, max_scroll);
+    let Some(focused_index) = sidebar_windows.iter().position(|w| w.is_focused) else {
+        return scroll_offset;
+    };
+
+    let before = stack_offset_before(position, dims, gap, focused_index);
+    let size = stack_axis_size(position, dims[focused_index]);
+    let min_visible_offset = (before + size - viewport).clamp(0, max_scroll);
+    let max_visible_offset = before.clamp(0, max_scroll);
+
+    if min_visible_offset > max_visible_offset {
+        min_visible_offset
+    } else {
+        if scroll_offset < min_visible_offset {
+            scroll_offset = min_visible_offset;
+        }
+        if scroll_offset > max_visible_offset {
+            scroll_offset = max_visible_offset;
+        }
+        scroll_offset
+    }
+}
+
+fn is_at_position(window: &Window, x: f64, y: f64) -> bool {
+    window
+        .layout
+        .tile_pos_in_workspace_view
+        .is_some_and(|(cur_x, cur_y)| (cur_x - x).abs() < 0.01 && (cur_y - y).abs() < 0.01)
+}
+
+pub fn plan_layout(input: &LayoutInput) -> LayoutPlan {
+    let LayoutInput {
+        windows: all_windows,
+        workspace_id: current_ws,
+        screen,
+        margins,
+        config,
+        state,
+    } = *input;
+    let display = screen.size();
+    let sidebar_ids: Vec<u64> = state.windows.iter().map(|w| w.id).collect();
+    let mut sidebar_windows: Vec<_> = all_windows
+        .iter()
+        .filter(|w| {
+            w.is_floating && w.workspace_id == Some(current_ws) && sidebar_ids.contains(&w.id)
+        })
+        .collect();
+
+    // Sort by ID for stable ordering
+    sidebar_windows.sort_by_key(|w| {
+        sidebar_ids
+            .iter()
+            .position(|id| *id == w.id)
+            .unwrap_or(usize::MAX)
+    });
+    if state.is_flipped {
+        sidebar_windows.reverse();
+    }
+
+    let position = config.interaction.position;
+    let gap = config.geometry.gap;
+    let focused_sidebar_id = sidebar_windows.iter().find(|w| w.is_focused).map(|w| w.id);
+    let active_maximized_window = match (state.is_hidden, state.maximized_window_id) {
+        (false, maximized) => maximized,
+        (true, Some(maximized_id)) if focused_sidebar_id == Some(maximized_id) => {
+            Some(maximized_id)
+        }
+        _ => None,
+    };
+    let mut dims: Vec<WindowTarget> = sidebar_windows
+        .iter()
+        .map(|window| resolve_dimensions(window, config, display))
+        .collect();
+    let ordered_ids: Vec<u64> = sidebar_windows.iter().map(|window| window.id).collect();
+    let maximized_in_current_layout = state
+        .maximized_window_id
+        .is_some_and(|id| ordered_ids.contains(&id));
+
+    apply_maximize_sizes(
+        position,
+        &mut dims,
+        &ordered_ids,
+        active_maximized_window,
+        display,
+        &margins,
+        gap,
+    );
+    let viewport = viewport_size(position, display, &margins);
+    if config.geometry.overflow == OverflowStrategy::Shrink {
+        shrink_to_viewport(position, &mut dims, viewport, gap);
+    }
+    // Only ever shrinks, so whatever fit before still fits
+    for dims in &mut dims {
+        dims.width = screen.snap_size(dims.width);
+        dims.height = screen.snap_size(dims.height);
+    }
+    // A shrunk stack always fits, unless the gaps alone fill the viewport and it has to scroll
+    let scroll_offset =
+        resolve_scroll_offset(state, position, &dims, &sidebar_windows, viewport, gap);
+
+    let mut windows = Vec::with_capacity(sidebar_windows.len());
+    let mut actions = Vec::new();
+    let mut current_stack_offset = 0;
+
+    for (index, window) in sidebar_windows.iter().enumerate() {
+        let dims = dims[index];
+
+        let active_peek = if window.is_focused {
+            resolve_rule_focus_peek(
+                &config.window_rule,
+                window,
+                config.interaction.get_focus_peek(),
+            )
+        } else {
+            resolve_rule_peek(&config.window_rule, window, config.interaction.peek)
+        };
+
+        let (mut target_x, mut target_y) = calculate_coordinates(
+            position,
+            dims,
+            display,
+            current_stack_offset,
+            active_peek,
+            state,
+            &margins,
+        );
+        match position {
+            SidebarPosition::Left | SidebarPosition::Right => {
+                target_y += scroll_offset;
+            }
+            SidebarPosition::Top | SidebarPosition::Bottom => {
+                target_x -= scroll_offset;
+            }
+        }
+
+        match position {
+            SidebarPosition::Left | SidebarPosition::Right => {
+                current_stack_offset += dims.height + gap;
+            }
+            SidebarPosition::Top | SidebarPosition::Bottom => {
+                current_stack_offset += dims.width + gap;
+            }
+        }
+
+        let (current_w, current_h) = window.layout.window_size;
+        if maximized_in_current_layout || config.geometry.overflow == OverflowStrategy::Shrink {
+            match position {
+                SidebarPosition::Left | SidebarPosition::Right if current_h != dims.height => {
+                    actions.push(Action::SetWindowHeight {
+                        change: SizeChange::SetFixed(dims.height),
+                        id: Some(window.id),
+                    });
+                }
+                SidebarPosition::Top | SidebarPosition::Bottom if current_w != dims.width => {
+                    actions.push(Action::SetWindowWidth {
+                        change: SizeChange::SetFixed(dims.width),
+                        id: Some(window.id),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let (target_x, target_y) = (screen.snap(target_x), screen.snap(target_y));
+        if !is_at_position(window, target_x, target_y) {
+            actions.push(Action::MoveFloatingWindow {
+                id: Some(window.id),
+                x: PositionChange::SetFixed(target_x),
+                y: PositionChange::SetFixed(target_y),
+            });
+        }
+
+        windows.push(PlannedWindow {
+            id: window.id,
+            app_id: window.app_id.clone(),
+            title: window.title.clone(),
+            is_focused: window.is_focused,
+            x: target_x,
+            y: target_y,
+            width: dims.width,
+            height: dims.height,
+        });
+    }
+
+    LayoutPlan {
+        screen,
+        workspace_id: current_ws,
+        scroll_offset,
+        windows,
+        actions,
+    }
+}
+
+/// Checks what every plan has to satisfy, for property tests and fuzzing `plan_layout`:
+/// windows don't overlap, have a size, and stay inside the margins whenever they fit (and aren't
+/// hidden), with the focused window always scrolled into view.
+#[cfg(any(test, feature = "test-utils"))]
+pub fn check_invariants(input: &LayoutInput, plan: &LayoutPlan) -> Result<(), String> {
+    // Positions are snapped to physical pixels, which moves them by less than a logical pixel
+    const EPSILON: f64 = 1.0;
+    let position = input.config.interaction.position;
+    let gap = f64::from(input.config.geometry.gap);
+    let (sw, sh) = input.screen.size();
+    let margins = &input.margins;
+
+    // (start, size) on the stack axis, then the same across it
+    let along = |w: &PlannedWindow| match is_vertical(position) {
+        true => (w.y, f64::from(w.height)),
+        false => (w.x, f64::from(w.width)),
+    };
+    let across = |w: &PlannedWindow| match is_vertical(position) {
+        true => (w.x, f64::from(w.width), sw),
+        false => (w.y, f64::from(w.height), sh),
+    };
+    let (viewport_start, viewport_end) = match is_vertical(position) {
+        true => (margins.top, sh - margins.bottom),
+        false => (margins.left, sw - margins.right),
+    };
+    let (viewport_start, viewport_end) = (f64::from(viewport_start), f64::from(viewport_end));
+    let in_viewport = |(start, size): (f64, f64)| {
+        start >= viewport_start - EPSILON && start + size <= viewport_end + EPSILON
+    };
+
+    for window in &plan.windows {
+        if window.width < 1 || window.height < 1 {
+            return Err(format!(
+                "window {} is {}x{}",
+                window.id, window.width, window.height
+            ));
+        }
+    }
+
+    let mut spans: Vec<_> = plan.windows.iter().map(|w| (along(w), w.id)).collect();
+    spans.sort_by(|a, b| a.0.0.total_cmp(&b.0.0));
+    for pair in spans.windows(2) {
+        let ((start, size), id) = pair[0];
+        let ((next_start, _), next_id) = pair[1];
+        if next_start + EPSILON < start + size + gap {
+            return Err(format!("windows {} and {} overlap", id, next_id));
+        }
+    }
+
+    if input.state.is_hidden || plan.windows.is_empty() {
+        return Ok(());
+    }
+
+    for window in &plan.windows {
+        let (start, size, screen) = across(window);
+        if size <= f64::from(screen)
+            && (start < -EPSILON || start + size > f64::from(screen) + EPSILON)
+        {
+            return Err(format!(
+                "window {} sticks out of the screen sideways",
+                window.id
+            ));
+        }
+    }
+
+    let stack_length =
+        spans.iter().map(|((_, size), _)| size).sum::<f64>() + gap * (spans.len() - 1) as f64;
+    // Shrinking, and maximizing, size the windows to fit unless even 1px each doesn't
+    let maximized = input
+        .state
+        .maximized_window_id
+        .is_some_and(|id| plan.windows.iter().any(|w| w.id == id));
+    let smallest_stack = spans.len() as f64 + gap * (spans.len() - 1) as f64;
+    if (input.config.geometry.overflow == OverflowStrategy::Shrink || maximized)
+        && smallest_stack <= viewport_end - viewport_start
+        && stack_length > viewport_end - viewport_start + EPSILON
+    {
+        return Err(format!(
+            "stack of {}px doesn't fit the {}px viewport",
+            stack_length,
+            viewport_end - viewport_start
+        ));
+    }
+
+    if stack_length <= viewport_end - viewport_start
+        && let Some((_, id)) = spans.iter().find(|(span, _)| !in_viewport(*span))
+    {
+        return Err(format!(
+            "window {} is outside the viewport although the stack fits",
+            id
+        ));
+    }
+
+    if let Some(focused) = plan.windows.iter().find(|w| w.is_focused)
+        && along(focused).1 <= viewport_end - viewport_start
+        && !in_viewport(along(focused))
+    {
+        return Err(format!(
+            "focused window {} is scrolled out of view",
+            focused.id
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Bar, Dimension, Size};
+    use crate::state::WindowState;
+    use crate::test_utils::{mock_config, mock_window};
+    use niri_ipc::{Layer, LayerSurfaceKeyboardInteractivity};
+
+    const SCREEN: Screen = Screen {
+        x: 0,
+        y: 0,
+        width: 1920,
+        height: 1080,
+        scale: 1.0,
+    };
+
+    fn tracked(ids: &[u64]) -> AppState {
+        AppState {
+            windows: ids
+                .iter()
+                .map(|id| WindowState {
+                    id: *id,
+                    width: 300,
+                    height: 200,
+                    is_floating: true,
+                    position: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_layout_stacks_from_the_bottom() {
+        let windows = vec![
+            mock_window(1, false, true, 1, None),
+            mock_window(2, true, true, 1, None),
+        ];
+        let config = mock_config();
+        let state = tracked(&[1, 2]);
+
+        let plan = plan_layout(&LayoutInput {
+            windows: &windows,
+            workspace_id: 1,
+            screen: SCREEN,
+            margins: config.margins,
+            config: &config,
+            state: &state,
+        });
+
+        let positions: Vec<_> = plan.windows.iter().map(|w| (w.id, w.x, w.y)).collect();
+        assert_eq!(positions, vec![(1, 1600.0, 830.0), (2, 1600.0, 620.0)]);
+        assert_eq!(plan.actions.len(), 2);
+    }
+
+    #[test]
+    fn test_plan_layout_ignores_untracked_tiled_and_other_workspace_windows() {
+        let windows = vec![
+            mock_window(1, false, true, 1, None),
+            mock_window(2, false, false, 1, None),
+            mock_window(3, false, true, 2, None),
+            mock_window(4, false, true, 1, None),
+        ];
+        let config = mock_config();
+        let state = tracked(&[1, 2, 3]);
+
+        let plan = plan_layout(&LayoutInput {
+            windows: &windows,
+            workspace_id: 1,
+            screen: SCREEN,
+            margins: config.margins,
+            config: &config,
+            state: &state,
+        });
+
+        assert_eq!(plan.windows.len(), 1);
+        assert_eq!(plan.windows[0].id, 1);
+        assert_eq!(plan.workspace_id, 1);
+    }
+
+    #[test]
+    fn test_plan_layout_snaps_positions_to_physical_pixels() {
+        let windows = vec![mock_window(1, false, true, 1, None)];
+        let mut config = mock_config();
+        config.margins.right = 21;
+        let state = tracked(&[1]);
+        let screen = Screen {
+            x: 1920,
+            y: 0,
+            scale: 1.25,
+            ..SCREEN
+        };
+
+        let plan = plan_layout(&LayoutInput {
+            windows: &windows,
+            workspace_id: 1,
+            screen,
+            margins: config.margins,
+            config: &config,
+            state: &state,
+        });
+
+        // 1920 - 300 - 21 = 1599 logical is 1998.75 physical, which rounds to 1999.
+        // The output origin doesn't shift positions, they are relative to the output.
+        assert_eq!(plan.windows[0].x, 1999.0 / 1.25);
+        // 1080 - 200 - 50 = 830 logical is 1037.5 physical, which rounds to 1038
+        assert_eq!(plan.windows[0].y, 1038.0 / 1.25);
+    }
+
+    #[test]
+    fn test_plan_layout_snaps_sizes_to_physical_pixels() {
+        let windows = vec![
+            mock_window(1, false, true, 1, None),
+            mock_window(2, true, true, 1, None),
+        ];
+        let mut config = mock_config();
+        config.geometry.width = 301.into();
+        config.geometry.height = 203.into();
+        config.geometry.overflow = OverflowStrategy::Shrink;
+        let state = tracked(&[1, 2]);
+        let screen = Screen {
+            scale: 1.25,
+            ..SCREEN
+        };
+
+        let plan = plan_layout(&LayoutInput {
+            windows: &windows,
+            workspace_id: 1,
+            screen,
+            margins: config.margins,
+            config: &config,
+            state: &state,
+        });
+
+        for window in &plan.windows {
+            assert_eq!(window.width, 300);
+            assert_eq!(window.height, 200);
+            assert_eq!((f64::from(window.width) * 1.25).fract(), 0.0);
+            assert_eq!((f64::from(window.height) * 1.25).fract(), 0.0);
+        }
+        for action in &plan.actions {
+            if let Action::SetWindowHeight {
+                change: SizeChange::SetFixed(height),
+                ..
+            } = action
+            {
+                assert_eq!((f64::from(*height) * 1.25).fract(), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_screen_snap_size() {
+        let screen = Screen {
+            scale: 1.25,
+            ..SCREEN
+        };
+        assert_eq!(screen.snap_size(203), 200);
+        assert_eq!(screen.snap_size(200), 200);
+        assert_eq!(SCREEN.snap_size(203), 203);
+        // Too far from the grid to be worth it
+        let odd = Screen {
+            scale: 1.0 + 1.0 / 120.0,
+            ..SCREEN
+        };
+        assert_eq!(odd.snap_size(203), 203);
+    }
+
+    #[test]
+    fn test_screen_snap_keeps_integer_scale_exact() {
+        assert_eq!(SCREEN.snap(1601), 1601.0);
+        let hidpi = Screen {
+            scale: 2.0,
+            ..SCREEN
+        };
+        assert_eq!(hidpi.snap(1601), 1601.0);
+    }
+
+    #[test]
+    fn test_resolve_margins_ignores_bars_without_auto_margins() {
+        let mut config = mock_config();
+        config.bar.push(Bar {
+            namespace: None,
+            output: None,
+            edge: Edge::Top,
+            size: 30,
+        });
+
+        assert_eq!(resolve_margins(&config, Some("eDP-1"), &[]).top, 50);
+    }
+
+    #[test]
+    fn test_resolve_margins_adds_bars_on_current_output() {
+        let mut config = mock_config();
+        config.margins.auto_margins = true;
+        config.bar = vec![
+            Bar {
+                namespace: Some("waybar".into()),
+                output: None,
+                edge: Edge::Top,
+                size: 30,
+            },
+            Bar {
+                namespace: None,
+                output: Some("eDP-1".into()),
+                edge: Edge::Right,
+                size: 40,
+            },
+            Bar {
+                namespace: None,
+                output: Some("HDMI-A-1".into()),
+                edge: Edge::Bottom,
+                size: 40,
+            },
+        ];
+        let layers = vec![LayerSurface {
+            namespace: "waybar".into(),
+            output: "HDMI-A-1".into(),
+            layer: Layer::Top,
+            keyboard_interactivity: LayerSurfaceKeyboardInteractivity::None,
+        }];
+
+        let laptop = resolve_margins(&config, Some("eDP-1"), &layers);
+        assert_eq!((laptop.top, laptop.right, laptop.bottom), (50, 60, 50));
+
+        let monitor = resolve_margins(&config, Some("HDMI-A-1"), &layers);
+        assert_eq!((monitor.top, monitor.right, monitor.bottom), (80, 20, 90));
+    }
+
+    /// splitmix64, so failures reproduce from the printed seed without extra dependencies.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        fn range(&mut self, low: i32, high: i32) -> i32 {
+            low + (self.next() % (high - low + 1) as u64) as i32
+        }
+
+        fn chance(&mut self, percent: u64) -> bool {
+            self.next() % 100 < percent
+        }
+
+        fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+            items[self.next() as usize % items.len()]
+        }
+    }
+
+    struct Case {
+        windows: Vec<Window>,
+        screen: Screen,
+        config: Config,
+        state: AppState,
+    }
+
+    fn random_case(rng: &mut Rng) -> Case {
+        let mut config = mock_config();
+        let screen = Screen {
+            x: rng.range(-4000, 4000),
+            y: 0,
+            width: rng.range(200, 4000),
+            height: rng.range(200, 2500),
+            scale: rng.pick(&[1.0, 1.25, 1.5, 2.0]),
+        };
+        config.interaction.position = rng.pick(&[
+            SidebarPosition::Left,
+            SidebarPosition::Right,
+            SidebarPosition::Top,
+            SidebarPosition::Bottom,
+        ]);
+        config.interaction.peek = rng.range(0, 50);
+        config.geometry.overflow = rng.pick(&[OverflowStrategy::Scroll, OverflowStrategy::Shrink]);
+        config.geometry.gap = rng.range(0, 40);
+        config.margins = Margins {
+            top: rng.range(0, screen.height / 4),
+            right: rng.range(0, screen.width / 4),
+            bottom: rng.range(0, screen.height / 4),
+            left: rng.range(0, screen.width / 4),
+            auto_margins: false,
+        };
+        // Across the stack windows fit between the margins, along it anything goes
+        let cross = match is_vertical(config.interaction.position) {
+            true => screen.width - config.margins.left - config.margins.right,
+            false => screen.height - config.margins.top - config.margins.bottom,
+        };
+        let size = |rng: &mut Rng, limit: i32| match rng.chance(30) {
+            true => Size::Percent(f64::from(rng.range(1, 60))).into(),
+            false => rng.range(1, limit).into(),
+        };
+        let along = size(rng, 1200);
+        let mut across: Dimension = size(rng, cross);
+        across.max = Some(Size::Pixels(cross));
+        (config.geometry.width, config.geometry.height) =
+            match is_vertical(config.interaction.position) {
+                true => (across, along),
+                false => (along, across),
+            };
+
+        let count = rng.range(0, 8) as u64;
+        let focused = rng.range(0, count as i32 + 1) as u64;
+        let mut windows = vec![];
+        for id in 1..=count {
+            let position = (
+                f64::from(rng.range(-100, 3000)),
+                f64::from(rng.range(-100, 3000)),
+            );
+            let mut window = mock_window(id, id == focused, true, 1, Some(position));
+            window.layout.window_size = (rng.range(1, 1500), rng.range(1, 1500));
+            windows.push(window);
+        }
+        // Windows the layout has to leave alone
+        windows.push(mock_window(100, false, false, 1, None));
+        windows.push(mock_window(101, false, true, 2, None));
+
+        let mut state = tracked(&(1..=count).collect::<Vec<_>>());
+        state.windows.push(tracked(&[101]).windows[0].clone());
+        state.is_flipped = rng.chance(50);
+        state.is_hidden = rng.chance(20);
+        state.scroll_offset = rng.range(-500, 5000);
+        if count > 0 && rng.chance(30) {
+            state.maximized_window_id = Some(rng.range(1, count as i32) as u64);
+        }
+
+        Case {
+            windows,
+            screen,
+            config,
+            state,
+        }
+    }
+
+    #[test]
+    fn test_plan_layout_invariants_hold_for_random_layouts() {
+        for seed in 0..5000 {
+            let case = random_case(&mut Rng(seed));
+            let input = LayoutInput {
+                windows: &case.windows,
+                workspace_id: 1,
+                screen: case.screen,
+                margins: case.config.margins,
+                config: &case.config,
+                state: &case.state,
+            };
+
+            let plan = plan_layout(&input);
+
+            if let Err(violation) = check_invariants(&input, &plan) {
+                panic!(
+                    "seed {}: {}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}",
+                    seed,
+                    violation,
+                    (
+                        case.config.interaction.position,
+                        case.state.maximized_window_id,
+                        case.state.is_flipped
+                    ),
+                    case.screen,
+                    case.config.geometry,
+                    case.config.margins,
+                    plan.windows
+                        .iter()
+                        .map(|w| (w.id, w.is_focused, w.x, w.y, w.width, w.height))
+                        .collect::<Vec<_>>()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_shrink_to_viewport_fills_it_exactly_for_random_stacks() {
+        let mut rng = Rng(7);
+        for _ in 0..2000 {
+            let count = rng.range(1, 10) as usize;
+            let gap = rng.range(0, 40);
+            let viewport = rng.range(0, 2000);
+            let mut dims: Vec<_> = (0..count)
+                .map(|_| WindowTarget {
+                    width: 300,
+                    height: rng.range(1, 1500),
+                })
+                .collect();
+            let before = stack_length(SidebarPosition::Right, &dims, gap);
+
+            shrink_to_viewport(SidebarPosition::Right, &mut dims, viewport, gap);
+
+            let after = stack_length(SidebarPosition::Right, &dims, gap);
+            assert!(dims.iter().all(|d| d.height >= 1 && d.width == 300));
+            if before <= viewport || viewport - gap * (count as i32 - 1) < count as i32 {
+                assert!(after <= before);
+            } else {
+                assert_eq!(after, viewport, "{:?}", dims);
+            }
+        }
+    }
+
+    #[test]
+    fn test_maximize_sizes_fit_when_the_minimum_leaves_no_room() {
+        let margins = Margins {
+            top: 0,
+            right: 5,
+            bottom: 0,
+            left: 24,
+            auto_margins: false,
+        };
+        let mut dims = vec![
+            WindowTarget {
+                width: 518,
+                height: 300,
+            };
+            4
+        ];
+
+        apply_maximize_sizes(
+            SidebarPosition::Top,
+            &mut dims,
+            &[1, 2, 3, 4],
+            Some(3),
+            (284, 1000),
+            &margins,
+            22,
+        );
+
+        // 284 - 24 - 5 - 3 * 22 leaves 189px, too little for 60px per unmaximized window
+        assert_eq!(stack_length(SidebarPosition::Top, &dims, 22), 255);
+        assert!(dims[2].width >= dims[0].width);
+    }
+
+    #[test]
+    fn test_shrink_scrolls_to_the_focused_window_when_gaps_fill_the_viewport() {
+        let windows: Vec<_> = (1..=4)
+            .map(|id| mock_window(id, id == 4, true, 1, None))
+            .collect();
+        let mut config = mock_config();
+        config.geometry.overflow = OverflowStrategy::Shrink;
+        config.geometry.gap = 400;
+        let state = tracked(&[1, 2, 3, 4]);
+
+        let plan = plan_layout(&LayoutInput {
+            windows: &windows,
+            workspace_id: 1,
+            screen: SCREEN,
+            margins: config.margins,
+            config: &config,
+            state: &state,
+        });
+
+        assert!(plan.scroll_offset > 0);
+        let focused = plan.windows.iter().find(|w| w.is_focused).unwrap();
+        assert!(focused.y >= f64::from(config.margins.top));
+    }
+}
@@ -1,6 +1,11 @@
 pub mod commands;
 pub mod config;
+pub mod hooks;
+pub mod kdl;
+pub mod layout;
+pub mod log;
 pub mod niri;
+pub mod recording;
 pub mod state;
 pub mod window_rules;
 
@@ -19,7 +24,10 @@ pub struct Ctx<C: NiriClient> {
     pub state: AppState,
     pub config: Config,
     pub socket: C,
-    pub cache_dir: PathBuf,
+    /// Files tied to the running niri instance, like the lock and the state with its window ids.
+    pub runtime_dir: PathBuf,
+    /// Data that outlives niri sessions, like snapshots.
+    pub state_dir: PathBuf,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
@@ -28,8 +36,8 @@ pub enum Direction {
     Prev,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct WindowTarget {
-    width: i32,
-    height: i32,
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum PlanFormat {
+    Table,
+    Json,
 }
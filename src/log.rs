@@ -0,0 +1,315 @@
+//! Leveled logging to stderr and a rotating log file in the state dir.
+//!
+//! Commands run from niri binds have no terminal, so everything at the configured level also goes
+//! to `niri-sidebar.log`. Under systemd, stderr lines get syslog priority prefixes so journald
+//! picks up the levels.
+
+use fslock::LockFile;
+use std::fmt::{self, Arguments};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOG_FILE: &str = "niri-sidebar.log";
+// Rotated once it grows past this, keeping `MAX_ROTATED_FILES` older files around
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+const MAX_ROTATED_FILES: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    const ALL: [Level; 5] = [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    /// sd-daemon(3) prefix, journald strips it and records the priority.
+    fn syslog_priority(self) -> u8 {
+        match self {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        }
+    }
+
+    /// `base` moved up by `verbose` and down by `quiet` steps, e.g. from `-vv` or `-q`.
+    pub fn adjusted(self, verbose: u8, quiet: u8) -> Level {
+        let index = (self as i32 + i32::from(verbose) - i32::from(quiet))
+            .clamp(0, Level::ALL.len() as i32 - 1);
+        Level::ALL[index as usize]
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Level::ALL
+            .into_iter()
+            .find(|level| level.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown log level: {:?}", s))
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+struct Logger {
+    level: Level,
+    journald: bool,
+    file: Option<Mutex<LogFile>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Sets up logging, messages before this (and in tests) go to stderr at info level.
+pub fn init(level: Level, state_dir: Option<&Path>) {
+    let file = state_dir.and_then(|dir| LogFile::open(dir.join(LOG_FILE)).ok());
+    let _ = LOGGER.set(Logger {
+        level,
+        journald: std::env::var_os("JOURNAL_STREAM").is_some(),
+        file: file.map(Mutex::new),
+    });
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= LOGGER.get().map_or(Level::Info, |l| l.level)
+}
+
+pub fn log(level: Level, args: Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let logger = LOGGER.get();
+
+    if logger.is_some_and(|l| l.journald) {
+        eprintln!("<{}>{}", level.syslog_priority(), args);
+    } else {
+        eprintln!("niri-sidebar [{}] {}", level, args);
+    }
+
+    if let Some(file) = logger.and_then(|l| l.file.as_ref())
+        && let Ok(mut file) = file.lock()
+    {
+        let line = format!(
+            "{} {:5} [{}] {}\n",
+            format_timestamp(SystemTime::now()),
+            level,
+            std::process::id(),
+            args
+        );
+        file.write(line.as_bytes());
+    }
+}
+
+/// The log file of every niri-sidebar process at once, e.g. a `listen` daemon per niri session
+/// and the commands run from binds. So its size and which file is current are always read from
+/// the path, another process may have grown or rotated it.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self { path, file })
+    }
+
+    // Logging must never take the sidebar down, so failures here are dropped
+    fn write(&mut self, line: &[u8]) {
+        let _ = self.follow_rotation();
+        let _ = self.file.write_all(line);
+    }
+
+    fn follow_rotation(&mut self) -> std::io::Result<()> {
+        if !self.is_current() {
+            *self = LogFile::open(self.path.clone())?;
+        }
+        if fs::metadata(&self.path)?.len() >= MAX_LOG_SIZE {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Whether the open file is still the one at the path.
+    fn is_current(&self) -> bool {
+        match (fs::metadata(&self.path), self.file.metadata()) {
+            (Ok(at_path), Ok(open)) => at_path.dev() == open.dev() && at_path.ino() == open.ino(),
+            _ => false,
+        }
+    }
+
+    /// `x.log` becomes `x.log.1`, `x.log.1` becomes `x.log.2` and so on.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        // Processes that found the file too large at the same time rotate it only once
+        let mut lock = LockFile::open(&suffixed_path(&self.path, "lock"))?;
+        lock.lock()?;
+        if self.is_current() && fs::metadata(&self.path)?.len() >= MAX_LOG_SIZE {
+            for n in (1..MAX_ROTATED_FILES).rev() {
+                let from = rotated_path(&self.path, n);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        *self = LogFile::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    suffixed_path(path, &n.to_string())
+}
+
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{}", suffix));
+    path.with_file_name(name)
+}
+
+/// UTC time in RFC 3339, e.g. `2024-03-01T12:00:00Z`.
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Error, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Warn, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Info, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Debug, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Trace, format_args!($($arg)*)) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_level_parsing_and_adjustment() {
+        assert_eq!("debug".parse(), Ok(Level::Debug));
+        assert_eq!(" WARN ".parse(), Ok(Level::Warn));
+        assert!("loud".parse::<Level>().is_err());
+
+        assert_eq!(Level::Info.adjusted(1, 0), Level::Debug);
+        assert_eq!(Level::Info.adjusted(5, 0), Level::Trace);
+        assert_eq!(Level::Info.adjusted(0, 2), Level::Error);
+        assert_eq!(Level::Warn.adjusted(1, 1), Level::Warn);
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+        assert_eq!(format_timestamp(leap_day), "2024-02-29T23:59:59Z");
+    }
+
+    #[test]
+    fn test_log_file_rotates() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join(LOG_FILE);
+        let mut file = LogFile::open(path.clone()).unwrap();
+        let line = vec![b'x'; MAX_LOG_SIZE as usize / 2 + 1];
+
+        for _ in 0..7 {
+            file.write(&line);
+        }
+
+        assert!(rotated_path(&path, 1).exists());
+        assert!(rotated_path(&path, MAX_ROTATED_FILES).exists());
+        assert!(!rotated_path(&path, MAX_ROTATED_FILES + 1).exists());
+        assert_eq!(fs::metadata(&path).unwrap().len(), line.len() as u64);
+    }
+
+    #[test]
+    fn test_log_file_follows_rotation_by_another_process() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join(LOG_FILE);
+        let mut daemon = LogFile::open(path.clone()).unwrap();
+        let mut command = LogFile::open(path.clone()).unwrap();
+        let line = vec![b'x'; MAX_LOG_SIZE as usize / 2 + 1];
+
+        // Together they fill the file, which only one of them wrote half of
+        daemon.write(&line);
+        command.write(&line);
+        daemon.write(b"rotated\n");
+        command.write(b"after\n");
+
+        assert_eq!(fs::read(&path).unwrap(), b"rotated\nafter\n");
+        assert_eq!(
+            fs::metadata(rotated_path(&path, 1)).unwrap().len(),
+            2 * line.len() as u64
+        );
+        assert!(!rotated_path(&path, 2).exists());
+    }
+}
@@ -1,10 +1,15 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, ArgGroup, Parser, Subcommand};
 use fslock::LockFile;
-use niri_sidebar::config::load_config;
-use niri_sidebar::state::{get_default_cache_dir, load_state};
-use niri_sidebar::{AppState, Ctx, config, niri::connect};
-use niri_sidebar::{Direction, commands};
+use niri_ipc::WorkspaceReferenceArg;
+use niri_sidebar::commands::MoveTarget;
+use niri_sidebar::config::{get_config_path, load_config};
+use niri_sidebar::log::{self, Level};
+use niri_sidebar::state::{get_runtime_dir, get_state_dir, load_session_state, migrate_cache_dir};
+use niri_sidebar::{AppState, Ctx, NiriClient, config, kdl, niri::connect};
+use niri_sidebar::{Direction, PlanFormat, commands};
+use std::path::PathBuf;
+use std::process::ExitCode;
 
 #[derive(Parser)]
 #[command(name = "niri-sidebar")]
@@ -12,6 +17,18 @@ use niri_sidebar::{Direction, commands};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Config file to use instead of ~/.config/niri-sidebar/config.toml
+    #[arg(long, global = true, env = "NIRI_SIDEBAR_CONFIG")]
+    config: Option<PathBuf>,
+    /// Directory for data kept across niri sessions, like snapshots
+    #[arg(long, global = true, env = "NIRI_SIDEBAR_STATE_DIR")]
+    state_dir: Option<PathBuf>,
+    /// Log more, repeat for even more (-vv)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Log less, repeat to only log errors (-qq)
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "verbose")]
+    quiet: u8,
 }
 
 #[derive(Subcommand)]
@@ -23,7 +40,18 @@ enum Commands {
     /// Reverse the order of windows in the stack
     Flip,
     /// Force re-stacking of windows
-    Reorder,
+    Reorder {
+        /// Print what would be done instead of moving any windows
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(long, value_enum, default_value_t = PlanFormat::Table)]
+        format: PlanFormat,
+    },
+    /// Print the planned sidebar layout without moving any windows
+    Layout {
+        #[arg(long, value_enum, default_value_t = PlanFormat::Table)]
+        format: PlanFormat,
+    },
     /// Close the focused window and reorder the sidebar
     Close,
     /// Focus and cycle through the windows in the sidebar
@@ -37,61 +65,227 @@ enum Commands {
     ToggleMaximizeFocusMode,
     /// Move the sidebar from a specific workspace to the current workspace
     MoveFrom {
-        #[arg()]
-        workspace: u64,
+        /// Workspace index on the focused output or workspace name. A bare number used to be
+        /// a workspace id, use `--id` for that now
+        #[arg(required_unless_present = "id")]
+        workspace: Option<WorkspaceReferenceArg>,
+        /// Workspace id, as shown by `niri msg workspaces`
+        #[arg(long, conflicts_with = "workspace")]
+        id: Option<u64>,
+    },
+    /// Move the sidebar to another workspace or output
+    #[command(group(ArgGroup::new("target").required(true)))]
+    MoveTo {
+        /// Workspace index on the focused output or workspace name
+        #[arg(long, group = "target")]
+        workspace: Option<WorkspaceReferenceArg>,
+        /// Workspace id, as shown by `niri msg workspaces`
+        #[arg(long, group = "target")]
+        workspace_id: Option<u64>,
+        /// Output name, the sidebar goes to the workspace shown on it
+        #[arg(long, group = "target")]
+        output: Option<String>,
+    },
+    /// Switch between the layout presets from the config
+    Preset {
+        #[command(subcommand)]
+        action: PresetAction,
+    },
+    /// Save, restore or list named sidebar arrangements
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
     },
     /// Generate a default config file if none exists
     Init,
+    /// Print niri window rules for the sidebar opacity, or write them to a file
+    GenerateNiriRules {
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Print a niri config snippet with binds, the daemon and window rules, or write it to a file
+    GenerateKdl {
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
     /// Run a daemon to listen for window close events
-    Listen,
+    Listen {
+        /// Write the niri events, responses and actions to this file, for bug reports
+        #[arg(long)]
+        record: Option<PathBuf>,
+    },
+    /// Re-run a recording from `listen --record` and show where the actions differ now
+    Replay { file: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum PresetAction {
+    /// Use a preset and re-layout right away
+    Set { name: String },
+    /// Use the next preset, in alphabetical order
+    Cycle,
+    /// Go back to the config without any preset
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum SnapshotAction {
+    /// Remember the current sidebar windows, their order and flags
+    Save { name: String },
+    /// Bring the windows of a snapshot back into the sidebar in the saved order
+    Restore { name: String },
+    /// List saved snapshots
+    List,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
+    // Errors end up in the log file too, as commands run from binds have no terminal to print to
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            niri_sidebar::error!("{:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    // A state dir picked by hand never had its files in the old cache dir
+    let default_state_dir = cli.state_dir.is_none();
+    let state_dir = get_state_dir(cli.state_dir)?;
+    init_logging(cli.verbose, cli.quiet, &state_dir);
+
+    let config_path = match cli.config {
+        Some(path) => path,
+        None => get_config_path()?,
+    };
 
     // Init doesn't require locks or state loading
     if let Commands::Init = cli.command {
-        return config::init_config();
+        return config::init_config(&config_path);
+    }
+    if let Commands::GenerateNiriRules { output } = &cli.command {
+        return kdl::write_or_print(
+            &kdl::niri_rules(&load_config(&config_path))?,
+            output.as_deref(),
+        );
+    }
+    if let Commands::Replay { file } = &cli.command {
+        return commands::replay(file);
+    }
+    if let Commands::GenerateKdl { output } = &cli.command {
+        let binary = std::env::current_exe()?.canonicalize()?;
+        let snippet = kdl::generate_kdl(&load_config(&config_path), &binary.to_string_lossy())?;
+        return kdl::write_or_print(&snippet, output.as_deref());
     }
 
-    let cache_dir = get_default_cache_dir()?;
-    let mut lock_path = cache_dir.clone();
+    let runtime_dir = get_runtime_dir()?;
+    let mut lock_path = runtime_dir.clone();
     lock_path.push("instance.lock");
     let mut lock_file = LockFile::open(&lock_path)?;
 
     // Listener will handle its own locking when it needs to write
-    if !matches!(cli.command, Commands::Listen) && !lock_file.try_lock()? {
+    if !matches!(cli.command, Commands::Listen { .. }) && !lock_file.try_lock()? {
         lock_file.lock()?;
     }
-    let config = load_config();
+    if default_state_dir && let Some(cache_dir) = dirs::cache_dir() {
+        let cache_dir = cache_dir.join("niri-sidebar");
+        if cache_dir.exists()
+            && let Err(e) = migrate_cache_dir(&cache_dir, &runtime_dir, &state_dir)
+        {
+            niri_sidebar::warn!("Could not migrate {:?}: {:#}", cache_dir, e);
+        }
+    }
+    let mut config = load_config(&config_path);
     // Listener will load state on demand
-    let state = if matches!(cli.command, Commands::Listen) {
+    let state = if matches!(cli.command, Commands::Listen { .. }) {
         AppState::default()
     } else {
-        load_state(&cache_dir)?
+        load_session_state(&runtime_dir, &state_dir)?
     };
-    let socket = connect()?;
+    let mut socket = connect()?;
+    // Listener picks its profile itself and switches it on hotplug
+    if !config.profile.is_empty() && !matches!(cli.command, Commands::Listen { .. }) {
+        config = config.for_outputs(&socket.get_outputs()?)?.0;
+    }
+    let base_config = config.clone();
+    let config = commands::apply_preset(&base_config, &state);
 
     let mut ctx = Ctx {
         state,
         config,
         socket,
-        cache_dir,
+        runtime_dir,
+        state_dir,
     };
 
     match cli.command {
         Commands::ToggleWindow => commands::toggle_window(&mut ctx)?,
         Commands::ToggleVisibility => commands::toggle_visibility(&mut ctx)?,
         Commands::Flip => commands::toggle_flip(&mut ctx)?,
-        Commands::Reorder => commands::reorder(&mut ctx)?,
+        Commands::Reorder {
+            dry_run: true,
+            format,
+        } => commands::layout(&mut ctx, format)?,
+        Commands::Reorder { .. } => commands::reorder(&mut ctx)?,
+        Commands::Layout { format } => commands::layout(&mut ctx, format)?,
         Commands::Close => commands::close(&mut ctx)?,
         Commands::Focus { direction } => commands::focus(&mut ctx, direction)?,
         Commands::Maximize => commands::maximize(&mut ctx)?,
         Commands::ToggleMaximizeFocusMode => commands::toggle_maximize_focus_mode(&mut ctx)?,
-        Commands::MoveFrom { workspace } => commands::move_from(&mut ctx, workspace)?,
-        Commands::Init => unreachable!(),
-        Commands::Listen => commands::listen(ctx)?,
+        Commands::MoveFrom { workspace, id } => {
+            let source = id.map(WorkspaceReferenceArg::Id).or(workspace);
+            commands::move_from(&mut ctx, source.expect("clap requires a workspace"))?
+        }
+        Commands::MoveTo {
+            workspace,
+            workspace_id,
+            output,
+        } => {
+            let target = match (
+                workspace_id.map(WorkspaceReferenceArg::Id).or(workspace),
+                output,
+            ) {
+                (Some(reference), _) => MoveTarget::Workspace(reference),
+                (None, Some(output)) => MoveTarget::Output(output),
+                (None, None) => unreachable!("clap requires a target"),
+            };
+            commands::move_to(&mut ctx, target)?
+        }
+        Commands::Preset { action } => match action {
+            PresetAction::Set { name } => {
+                commands::set_preset(&mut ctx, &base_config, Some(&name))?
+            }
+            PresetAction::Cycle => commands::cycle_preset(&mut ctx, &base_config)?,
+            PresetAction::Clear => commands::set_preset(&mut ctx, &base_config, None)?,
+        },
+        Commands::Snapshot { action } => match action {
+            SnapshotAction::Save { name } => commands::snapshot_save(&mut ctx, &name)?,
+            SnapshotAction::Restore { name } => commands::snapshot_restore(&mut ctx, &name)?,
+            SnapshotAction::List => commands::snapshot_list(&mut ctx)?,
+        },
+        Commands::Init
+        | Commands::GenerateNiriRules { .. }
+        | Commands::GenerateKdl { .. }
+        | Commands::Replay { .. } => unreachable!(),
+        Commands::Listen { record } => commands::listen(ctx, config_path, record)?,
     }
 
     Ok(())
 }
+
+/// `NIRI_SIDEBAR_LOG` sets the level, `-v` and `-q` go up and down from there.
+fn init_logging(verbose: u8, quiet: u8, state_dir: &std::path::Path) {
+    let from_env = std::env::var("NIRI_SIDEBAR_LOG")
+        .ok()
+        .map(|s| s.parse::<Level>());
+    let base = match &from_env {
+        Some(Ok(level)) => *level,
+        _ => Level::Info,
+    };
+    log::init(base.adjusted(verbose, quiet), Some(state_dir));
+    if let Some(Err(e)) = from_env {
+        niri_sidebar::warn!("Ignoring NIRI_SIDEBAR_LOG: {}", e);
+    }
+}
@@ -1,13 +1,19 @@
+use crate::layout::Screen;
 use anyhow::{Context, Result, bail};
-use niri_ipc::Action;
+use niri_ipc::state::{EventStreamState, EventStreamStatePart};
+use niri_ipc::{Action, Event, LayerSurface, Output};
 use niri_ipc::{Request, Response, socket::Socket};
 pub use niri_ipc::{Window, Workspace};
+use std::collections::HashMap;
 
 pub trait NiriClient {
     fn get_windows(&mut self) -> Result<Vec<Window>>;
     fn get_active_window(&mut self) -> Result<Window>;
     fn get_active_workspace(&mut self) -> Result<Workspace>;
-    fn get_screen_dimensions(&mut self) -> Result<(i32, i32)>;
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>>;
+    fn get_outputs(&mut self) -> Result<Vec<Output>>;
+    fn get_screen(&mut self) -> Result<Screen>;
+    fn get_layers(&mut self) -> Result<Vec<LayerSurface>>;
     fn send_action(&mut self, action: Action) -> Result<Response>;
 }
 
@@ -15,6 +21,27 @@ pub fn connect() -> Result<Socket> {
     Socket::connect().context("Failed to connect to Niri socket")
 }
 
+/// The logical rectangle and scale of an output, which has none while it is disabled.
+pub fn screen_of(output: &Output) -> Result<Screen> {
+    let logical = output
+        .logical
+        .as_ref()
+        .context("Output has no logical size")?;
+    Ok(Screen {
+        x: logical.x,
+        y: logical.y,
+        width: logical
+            .width
+            .try_into()
+            .context("Output width is out of range")?,
+        height: logical
+            .height
+            .try_into()
+            .context("Output height is out of range")?,
+        scale: logical.scale,
+    })
+}
+
 impl NiriClient for Socket {
     fn get_windows(&mut self) -> Result<Vec<Window>> {
         match self.send(Request::Windows)? {
@@ -41,7 +68,21 @@ impl NiriClient for Socket {
         }
     }
 
-    fn get_screen_dimensions(&mut self) -> Result<(i32, i32)> {
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        match self.send(Request::Workspaces)? {
+            Ok(Response::Workspaces(workspaces)) => Ok(workspaces),
+            _ => bail!("Unexpected response from Niri when fetching workspaces"),
+        }
+    }
+
+    fn get_outputs(&mut self) -> Result<Vec<Output>> {
+        match self.send(Request::Outputs)? {
+            Ok(Response::Outputs(outputs)) => Ok(outputs.into_values().collect()),
+            _ => bail!("Unexpected response from Niri when fetching outputs"),
+        }
+    }
+
+    fn get_screen(&mut self) -> Result<Screen> {
         let workspace = self.get_active_workspace()?;
         let target_output_name = workspace
             .output
@@ -53,23 +94,300 @@ impl NiriClient for Socket {
                     .values()
                     .find(|o| o.name == target_output_name)
                     .context("Output not found")?;
-
-                // Return the logical size
-                let logical = output
-                    .logical
-                    .as_ref()
-                    .context("Output has no logical size")?;
-                Ok((
-                    logical.width.try_into().unwrap_or(1920),
-                    logical.height.try_into().unwrap_or(1080),
-                ))
+                screen_of(output)
             }
             _ => bail!("Unexpected response from Niri when fetching outputs"),
         }
     }
 
+    fn get_layers(&mut self) -> Result<Vec<LayerSurface>> {
+        match self.send(Request::Layers)? {
+            Ok(Response::Layers(layers)) => Ok(layers),
+            _ => bail!("Unexpected response from Niri when fetching layers"),
+        }
+    }
+
+    fn send_action(&mut self, action: Action) -> Result<Response> {
+        crate::debug!("Sending {:?}", action);
+        let reply = self.send(Request::Action(action))?;
+        if let Err(e) = &reply {
+            crate::debug!("niri rejected the action: {}", e);
+        }
+        reply.map_err(|e| anyhow::anyhow!(e))
+    }
+}
+
+/// Answers queries from a model kept up to date by the event stream instead of asking niri.
+///
+/// Falls back to the wrapped client until the stream delivered the initial window and workspace
+/// lists, and after we sent an action until the model is seen to match niri again.
+pub struct EventStreamClient<C: NiriClient> {
+    inner: C,
+    model: EventStreamState,
+    has_windows: bool,
+    has_workspaces: bool,
+    stale: bool,
+    screen: Option<(String, Screen)>,
+}
+
+impl<C: NiriClient> EventStreamClient<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            model: EventStreamState::default(),
+            has_windows: false,
+            has_workspaces: false,
+            stale: false,
+            screen: None,
+        }
+    }
+
+    pub fn apply(&mut self, event: Event) {
+        match &event {
+            Event::WindowsChanged { .. } => self.has_windows = true,
+            Event::WorkspacesChanged { .. } => self.has_workspaces = true,
+            _ => {}
+        }
+        self.model.apply(event);
+    }
+
+    /// Checks whether the model caught up with the actions we sent, call this once all pending
+    /// events were applied.
+    ///
+    /// Events caused by an action can arrive after the burst that follows it, so instead of
+    /// trusting the burst this asks niri once and keeps falling back until the model matches.
+    pub fn mark_synced(&mut self) {
+        if !self.stale {
+            return;
+        }
+        match self.matches_niri() {
+            Ok(true) => self.stale = false,
+            Ok(false) => crate::debug!("Still waiting for the events of our last actions"),
+            Err(e) => crate::warn!("Could not check whether the model is current: {:#}", e),
+        }
+    }
+
+    fn matches_niri(&mut self) -> Result<bool> {
+        let mut workspaces: Vec<_> = self.model.workspaces.workspaces.values().cloned().collect();
+        workspaces.sort_by_key(|w| w.id);
+        let mut live_workspaces = self.inner.get_workspaces()?;
+        live_workspaces.sort_by_key(|w| w.id);
+        if workspaces != live_workspaces {
+            return Ok(false);
+        }
+
+        // Window has no PartialEq, its JSON form does
+        let mut windows: Vec<_> = self.model.windows.windows.values().collect();
+        windows.sort_by_key(|w| w.id);
+        let mut live_windows = self.inner.get_windows()?;
+        live_windows.sort_by_key(|w| w.id);
+        Ok(serde_json::to_value(windows)? == serde_json::to_value(live_windows)?)
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn window(&self, id: u64) -> Option<&Window> {
+        self.model.windows.windows.get(&id)
+    }
+
+    pub fn windows(&self) -> &HashMap<u64, Window> {
+        &self.model.windows.windows
+    }
+
+    fn is_synced(&self) -> bool {
+        self.has_windows && self.has_workspaces && !self.stale
+    }
+}
+
+impl<C: NiriClient> NiriClient for EventStreamClient<C> {
+    fn get_windows(&mut self) -> Result<Vec<Window>> {
+        if !self.is_synced() {
+            return self.inner.get_windows();
+        }
+        let mut windows: Vec<_> = self.model.windows.windows.values().cloned().collect();
+        windows.sort_by_key(|w| w.id);
+        Ok(windows)
+    }
+
+    fn get_active_window(&mut self) -> Result<Window> {
+        if !self.is_synced() {
+            return self.inner.get_active_window();
+        }
+        self.model
+            .windows
+            .windows
+            .values()
+            .find(|w| w.is_focused)
+            .cloned()
+            .context("No window focused")
+    }
+
+    fn get_active_workspace(&mut self) -> Result<Workspace> {
+        if !self.is_synced() {
+            return self.inner.get_active_workspace();
+        }
+        self.model
+            .workspaces
+            .workspaces
+            .values()
+            .find(|w| w.is_focused)
+            .cloned()
+            .context("No active workspace found")
+    }
+
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        if !self.is_synced() {
+            return self.inner.get_workspaces();
+        }
+        let mut workspaces: Vec<_> = self.model.workspaces.workspaces.values().cloned().collect();
+        workspaces.sort_by_key(|w| w.id);
+        Ok(workspaces)
+    }
+
+    fn get_screen(&mut self) -> Result<Screen> {
+        let output = self.get_active_workspace()?.output;
+        if let Some((cached_output, screen)) = &self.screen
+            && output.as_ref() == Some(cached_output)
+        {
+            return Ok(*screen);
+        }
+
+        let screen = self.inner.get_screen()?;
+        self.screen = output.map(|name| (name, screen));
+        Ok(screen)
+    }
+
+    // Outputs aren't part of the stream either, a fresh list is the only sign of a new mode or
+    // scale for the cached screen
+    fn get_outputs(&mut self) -> Result<Vec<Output>> {
+        let outputs = self.inner.get_outputs()?;
+        if let Some((name, screen)) = &self.screen {
+            let current = outputs
+                .iter()
+                .find(|o| &o.name == name)
+                .and_then(|o| screen_of(o).ok());
+            if current != Some(*screen) {
+                self.screen = None;
+            }
+        }
+        Ok(outputs)
+    }
+
+    // Layer surfaces aren't part of the stream and bars can move without any event
+    fn get_layers(&mut self) -> Result<Vec<LayerSurface>> {
+        self.inner.get_layers()
+    }
+
     fn send_action(&mut self, action: Action) -> Result<Response> {
-        self.send(Request::Action(action))?
-            .map_err(|e| anyhow::anyhow!(e))
+        self.stale = true;
+        self.inner.send_action(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{MockNiri, mock_window};
+
+    fn synced_client(
+        model_windows: Vec<Window>,
+        live_windows: Vec<Window>,
+    ) -> EventStreamClient<MockNiri> {
+        let mut client = EventStreamClient::new(MockNiri::new(live_windows));
+        let workspace = client.inner.get_active_workspace().unwrap();
+        client.apply(Event::WorkspacesChanged {
+            workspaces: vec![workspace],
+        });
+        client.apply(Event::WindowsChanged {
+            windows: model_windows,
+        });
+        client
+    }
+
+    #[test]
+    fn test_queries_fall_back_until_initial_state_received() {
+        let mut client =
+            EventStreamClient::new(MockNiri::new(vec![mock_window(1, true, false, 1, None)]));
+        client.apply(Event::WindowsChanged { windows: vec![] });
+
+        assert_eq!(client.get_windows().unwrap().len(), 1);
+        assert_eq!(client.get_active_window().unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_queries_are_answered_from_model() {
+        let mut client = synced_client(
+            vec![mock_window(2, true, true, 1, None)],
+            vec![mock_window(1, true, false, 1, None)],
+        );
+
+        let windows = client.get_windows().unwrap();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].id, 2);
+
+        client.apply(Event::WindowFocusChanged { id: None });
+        assert!(client.get_active_window().is_err());
+        assert_eq!(client.get_active_workspace().unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_actions_make_model_stale_until_it_matches_niri() {
+        let mut client = synced_client(
+            vec![mock_window(2, true, false, 1, None)],
+            vec![mock_window(2, true, true, 1, None)],
+        );
+
+        client
+            .send_action(Action::ToggleWindowFloating { id: Some(2) })
+            .unwrap();
+        assert!(client.get_windows().unwrap()[0].is_floating);
+
+        // A burst without the action's events leaves the model stale
+        client.mark_synced();
+        assert!(client.get_windows().unwrap()[0].is_floating);
+
+        client.apply(Event::WindowOpenedOrChanged {
+            window: mock_window(2, true, true, 1, None),
+        });
+        client.mark_synced();
+        client.inner.windows.clear();
+        assert_eq!(client.get_windows().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_querying_outputs_invalidates_the_screen() {
+        let mut client = synced_client(vec![], vec![]);
+        client.screen = Some((
+            "eDP-1".into(),
+            Screen {
+                x: 0,
+                y: 0,
+                width: 800,
+                height: 600,
+                scale: 2.0,
+            },
+        ));
+        assert_eq!(client.get_screen().unwrap().width, 800);
+
+        client.get_outputs().unwrap();
+
+        assert_eq!(client.get_screen().unwrap().width, 1920);
+    }
+
+    #[test]
+    fn test_querying_outputs_keeps_the_screen_until_it_changes() {
+        let mut client = synced_client(vec![], vec![]);
+        assert_eq!(client.get_screen().unwrap().scale, 1.0);
+        client.get_outputs().unwrap();
+        assert!(client.screen.is_some());
+
+        if let Some(logical) = &mut client.inner.outputs[0].logical {
+            logical.scale = 2.0;
+        }
+        client.get_outputs().unwrap();
+
+        assert!(client.screen.is_none());
     }
 }
@@ -0,0 +1,244 @@
+//! Recordings of what the `listen` daemon saw and did, to reproduce bugs with `replay`.
+//!
+//! A recording is a JSON line per [`Entry`]: every burst of events with the state and config it
+//! was handled with, followed by the niri queries and actions handling it took.
+
+use crate::layout::Screen;
+use crate::niri::NiriClient;
+use crate::{AppState, Config};
+use anyhow::{Context, Result, anyhow};
+use niri_ipc::{Action, Event, LayerSurface, Output, Response, Window, Workspace};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entry {
+    /// Events handled together, with the state and config right before handling them
+    Burst {
+        events: Vec<Event>,
+        state: AppState,
+        config: Config,
+        /// The outputs differed from the previous burst, which the events alone don't tell
+        #[serde(default)]
+        outputs_changed: bool,
+    },
+    /// The daemon reconnected to niri and re-laid out the sidebar from scratch
+    Resync { state: AppState, config: Config },
+    Query {
+        query: Query,
+        response: Result<Value, String>,
+    },
+    Action {
+        action: Action,
+        error: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Query {
+    Windows,
+    ActiveWindow,
+    ActiveWorkspace,
+    Workspaces,
+    Outputs,
+    Screen,
+    Layers,
+}
+
+/// Appends entries to a recording file, shared by every connection the daemon opens.
+#[derive(Clone)]
+pub struct Recorder {
+    file: Rc<RefCell<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Could not create {:?}", path))?;
+        Ok(Self {
+            file: Rc::new(RefCell::new(file)),
+        })
+    }
+
+    // A broken recording shouldn't take the sidebar down with it
+    pub fn record(&self, entry: &Entry) {
+        let result = serde_json::to_string(entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(self.file.borrow_mut(), "{}", line)?));
+        if let Err(e) = result {
+            crate::warn!("Failed to write to the recording: {:#}", e);
+        }
+    }
+}
+
+pub fn load_recording(path: &Path) -> Result<Vec<Entry>> {
+    let content = fs::read_to_string(path).with_context(|| format!("Could not read {:?}", path))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid entry on line {} of {:?}", index + 1, path))
+        })
+        .collect()
+}
+
+/// Passes everything through to the wrapped client, recording it if a recorder is set.
+pub struct RecordingClient<C: NiriClient> {
+    inner: C,
+    recorder: Option<Recorder>,
+}
+
+impl<C: NiriClient> RecordingClient<C> {
+    pub fn new(inner: C, recorder: Option<Recorder>) -> Self {
+        Self { inner, recorder }
+    }
+
+    fn query<T: Serialize>(
+        &mut self,
+        query: Query,
+        send: impl FnOnce(&mut C) -> Result<T>,
+    ) -> Result<T> {
+        let result = send(&mut self.inner);
+        if let Some(recorder) = &self.recorder {
+            let response = match &result {
+                Ok(value) => serde_json::to_value(value).map_err(|e| e.to_string()),
+                Err(e) => Err(format!("{:#}", e)),
+            };
+            recorder.record(&Entry::Query { query, response });
+        }
+        result
+    }
+}
+
+impl<C: NiriClient> NiriClient for RecordingClient<C> {
+    fn get_windows(&mut self) -> Result<Vec<Window>> {
+        self.query(Query::Windows, C::get_windows)
+    }
+
+    fn get_active_window(&mut self) -> Result<Window> {
+        self.query(Query::ActiveWindow, C::get_active_window)
+    }
+
+    fn get_active_workspace(&mut self) -> Result<Workspace> {
+        self.query(Query::ActiveWorkspace, C::get_active_workspace)
+    }
+
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        self.query(Query::Workspaces, C::get_workspaces)
+    }
+
+    fn get_outputs(&mut self) -> Result<Vec<Output>> {
+        self.query(Query::Outputs, C::get_outputs)
+    }
+
+    fn get_screen(&mut self) -> Result<Screen> {
+        self.query(Query::Screen, C::get_screen)
+    }
+
+    fn get_layers(&mut self) -> Result<Vec<LayerSurface>> {
+        self.query(Query::Layers, C::get_layers)
+    }
+
+    fn send_action(&mut self, action: Action) -> Result<Response> {
+        let recorded = self.recorder.as_ref().map(|_| action.clone());
+        let result = self.inner.send_action(action);
+        if let (Some(recorder), Some(action)) = (&self.recorder, recorded) {
+            let error = result.as_ref().err().map(|e| format!("{:#}", e));
+            recorder.record(&Entry::Action { action, error });
+        }
+        result
+    }
+}
+
+/// Answers queries with the responses recorded for the burst being replayed.
+///
+/// Responses are handed out in recorded order per kind of query, so an extra or missing query of
+/// one kind doesn't shift the answers to all the others.
+#[derive(Default)]
+pub struct ReplayClient {
+    responses: VecDeque<(Query, Result<Value, String>)>,
+    recorded_actions: Vec<(Action, Option<String>)>,
+    pub sent_actions: Vec<Action>,
+}
+
+impl ReplayClient {
+    /// Switches to the responses of the next burst, dropping whatever the last one didn't use.
+    pub fn load(
+        &mut self,
+        responses: VecDeque<(Query, Result<Value, String>)>,
+        recorded_actions: Vec<(Action, Option<String>)>,
+    ) {
+        self.responses = responses;
+        self.recorded_actions = recorded_actions;
+        self.sent_actions.clear();
+    }
+
+    fn respond<T: DeserializeOwned>(&mut self, query: Query) -> Result<T> {
+        let index = self
+            .responses
+            .iter()
+            .position(|(q, _)| *q == query)
+            .with_context(|| format!("The recording has no more responses to {:?}", query))?;
+        let (_, response) = self.responses.remove(index).expect("index is in range");
+        Ok(serde_json::from_value(response.map_err(|e| anyhow!(e))?)?)
+    }
+}
+
+impl NiriClient for ReplayClient {
+    fn get_windows(&mut self) -> Result<Vec<Window>> {
+        self.respond(Query::Windows)
+    }
+
+    fn get_active_window(&mut self) -> Result<Window> {
+        self.respond(Query::ActiveWindow)
+    }
+
+    fn get_active_workspace(&mut self) -> Result<Workspace> {
+        self.respond(Query::ActiveWorkspace)
+    }
+
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        self.respond(Query::Workspaces)
+    }
+
+    fn get_outputs(&mut self) -> Result<Vec<Output>> {
+        self.respond(Query::Outputs)
+    }
+
+    fn get_screen(&mut self) -> Result<Screen> {
+        self.respond(Query::Screen)
+    }
+
+    fn get_layers(&mut self) -> Result<Vec<LayerSurface>> {
+        self.respond(Query::Layers)
+    }
+
+    // Actions niri rejected back then are rejected again if they are sent in the same place
+    fn send_action(&mut self, action: Action) -> Result<Response> {
+        let error = self
+            .recorded_actions
+            .get(self.sent_actions.len())
+            .filter(|(recorded, _)| same_action(recorded, &action))
+            .and_then(|(_, error)| error.clone());
+        self.sent_actions.push(action);
+        match error {
+            Some(e) => Err(anyhow!(e)),
+            None => Ok(Response::Handled),
+        }
+    }
+}
+
+/// niri's actions don't implement `PartialEq`, but they compare fine as JSON.
+pub fn same_action(a: &Action, b: &Action) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
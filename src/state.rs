@@ -1,10 +1,23 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use fslock::LockFile;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+/// Version of the `state.json` layout, bump it and add a migration when a change needs one.
+pub const STATE_VERSION: u32 = 1;
+
+/// Upgrades a state file by one version, `MIGRATIONS[n]` turns version `n` into `n + 1`.
+const MIGRATIONS: &[fn(&mut Value) -> Result<()>] = &[migrate_unversioned];
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct AppState {
+    #[serde(default)]
+    pub version: u32,
     pub windows: Vec<WindowState>,
     #[serde(default)]
     pub ignored_windows: Vec<u64>,
@@ -18,9 +31,14 @@ pub struct AppState {
     pub is_flipped: bool,
     #[serde(default)]
     pub scroll_offset: i32,
+    #[serde(default)]
+    pub active_preset: Option<String>,
+    /// Keyed by window id, only for sidebar windows that were tiled before joining.
+    #[serde(default)]
+    pub tiled_origins: BTreeMap<u64, TiledOrigin>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct WindowState {
     pub id: u64,
     pub width: i32,
@@ -29,31 +47,306 @@ pub struct WindowState {
     pub position: Option<(f64, f64)>,
 }
 
-pub fn get_default_cache_dir() -> Result<PathBuf> {
-    let mut path = dirs::cache_dir().context("Could not find cache directory")?;
-    path.push("niri-sidebar");
+/// Where a window sat while it was tiled, to put it back there when it leaves the sidebar.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct TiledOrigin {
+    pub workspace_id: Option<u64>,
+    /// Column and tile index (1-based).
+    pub column: usize,
+    pub tile: usize,
+    /// Whether other windows shared the column, which decides if it still exists afterwards.
+    pub shared_column: bool,
+}
+
+/// The part of the state about how the sidebar is used rather than which windows are in it. Unlike
+/// window ids it stays valid when niri restarts, so it's also kept in the state dir.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Preferences {
+    #[serde(default)]
+    pub active_preset: Option<String>,
+    #[serde(default)]
+    pub maximize_focus_mode: bool,
+    #[serde(default)]
+    pub is_flipped: bool,
+}
+
+impl Preferences {
+    pub fn of(state: &AppState) -> Self {
+        Self {
+            active_preset: state.active_preset.clone(),
+            maximize_focus_mode: state.maximize_focus_mode,
+            is_flipped: state.is_flipped,
+        }
+    }
+}
+
+/// Per niri instance directory for the lock and state, so nested or test sessions of niri each
+/// get their own sidebar. `NIRI_SIDEBAR_RUNTIME_DIR` replaces it entirely.
+pub fn get_runtime_dir() -> Result<PathBuf> {
+    let path = match std::env::var_os("NIRI_SIDEBAR_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let mut path = dirs::runtime_dir()
+                .or_else(dirs::cache_dir)
+                .context("Could not find runtime directory")?;
+            path.push("niri-sidebar");
+            let socket = std::env::var_os(niri_ipc::socket::SOCKET_PATH_ENV).unwrap_or_default();
+            path.push(session_id(socket.as_encoded_bytes()));
+            path
+        }
+    };
+    create_dir(path)
+}
+
+/// Directory for data that outlives niri sessions, `override_dir` comes from `--state-dir`.
+pub fn get_state_dir(override_dir: Option<PathBuf>) -> Result<PathBuf> {
+    let path = match override_dir
+        .or_else(|| std::env::var_os("NIRI_SIDEBAR_STATE_DIR").map(PathBuf::from))
+    {
+        Some(dir) => dir,
+        None => {
+            let mut path = dirs::state_dir()
+                .or_else(dirs::data_local_dir)
+                .context("Could not find state directory")?;
+            path.push("niri-sidebar");
+            path
+        }
+    };
+    create_dir(path)
+}
+
+fn create_dir(path: PathBuf) -> Result<PathBuf> {
     if !path.exists() {
         fs::create_dir_all(&path)?;
     }
     Ok(path)
 }
 
-pub fn load_state(base_dir: &Path) -> Result<AppState> {
+/// FNV-1a of the niri socket path, stable across builds unlike the std hasher.
+fn session_id(socket: &[u8]) -> String {
+    let hash = socket.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+pub fn state_path(base_dir: &Path) -> PathBuf {
     let mut path = base_dir.to_path_buf();
     path.push("state.json");
-    if path.exists() {
-        let content = fs::read_to_string(path)?;
-        Ok(serde_json::from_str(&content).unwrap_or_default())
-    } else {
-        Ok(AppState::default())
+    path
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            version: STATE_VERSION,
+            windows: vec![],
+            ignored_windows: vec![],
+            maximized_window_id: None,
+            maximize_focus_mode: false,
+            is_hidden: false,
+            is_flipped: false,
+            scroll_offset: 0,
+            active_preset: None,
+            tiled_origins: BTreeMap::new(),
+        }
     }
 }
 
+/// Loads the state, a file that can't be read is moved to `state.json.bad` instead of lost.
+pub fn load_state(base_dir: &Path) -> Result<AppState> {
+    let path = state_path(base_dir);
+    if !path.exists() {
+        return Ok(AppState::default());
+    }
+
+    let content = fs::read_to_string(&path)?;
+    match parse_state(&content) {
+        Ok(state) => Ok(state),
+        Err(e) => {
+            let bad_path = path.with_extension("json.bad");
+            fs::rename(&path, &bad_path)?;
+            crate::warn!(
+                "Could not read {:?} ({:#}), moved it to {:?} and starting over",
+                path,
+                e,
+                bad_path
+            );
+            Ok(AppState::default())
+        }
+    }
+}
+
+fn parse_state(content: &str) -> Result<AppState> {
+    let mut value: Value = serde_json::from_str(content)?;
+    let version = match value.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .context("Invalid state version")?,
+    };
+
+    if version > STATE_VERSION {
+        // Written by a newer niri-sidebar, unknown fields are ignored so this usually still works
+        crate::warn!(
+            "State version {} is newer than the supported {}",
+            version,
+            STATE_VERSION
+        );
+    }
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        migration(&mut value)?;
+    }
+
+    let mut state: AppState = serde_json::from_value(value)?;
+    // Saved as what we understood of it, so a newer version migrates whatever we dropped
+    state.version = STATE_VERSION;
+    Ok(state)
+}
+
+/// Files from before versioning, every field added since then has a serde default.
+fn migrate_unversioned(value: &mut Value) -> Result<()> {
+    let Some(state) = value.as_object_mut() else {
+        bail!("State is not an object");
+    };
+    state.insert("version".into(), 1.into());
+    Ok(())
+}
+
 pub fn save_state(state: &AppState, base_dir: &Path) -> Result<()> {
-    let mut path = base_dir.to_path_buf();
-    path.push("state.json");
     let content = serde_json::to_string_pretty(state)?;
-    fs::write(path, content)?;
+    write_atomic(&state_path(base_dir), &content)
+}
+
+pub fn preferences_path(state_dir: &Path) -> PathBuf {
+    state_dir.join("preferences.json")
+}
+
+/// Loads the state of the current niri session, a new session starts out with the preferences
+/// saved in `state_dir`.
+pub fn load_session_state(runtime_dir: &Path, state_dir: &Path) -> Result<AppState> {
+    if state_path(runtime_dir).exists() {
+        return load_state(runtime_dir);
+    }
+
+    let preferences = load_preferences(state_dir)?;
+    Ok(AppState {
+        active_preset: preferences.active_preset,
+        maximize_focus_mode: preferences.maximize_focus_mode,
+        is_flipped: preferences.is_flipped,
+        ..AppState::default()
+    })
+}
+
+fn load_preferences(state_dir: &Path) -> Result<Preferences> {
+    let path = preferences_path(state_dir);
+    if !path.exists() {
+        return Ok(Preferences::default());
+    }
+
+    let content = fs::read_to_string(&path)?;
+    match serde_json::from_str(&content) {
+        Ok(preferences) => Ok(preferences),
+        Err(e) => {
+            crate::warn!("Ignoring {:?}: {:#}", path, e);
+            Ok(Preferences::default())
+        }
+    }
+}
+
+/// Call next to [`save_state`] whenever one of the [`Preferences`] changes.
+pub fn save_preferences(state: &AppState, state_dir: &Path) -> Result<()> {
+    let content = serde_json::to_string_pretty(&Preferences::of(state))?;
+    write_atomic(&preferences_path(state_dir), &content)
+}
+
+/// Moves what older versions kept in `cache_dir` (`~/.cache/niri-sidebar`) to where it lives now.
+/// Files that already exist in the new place win, and the old ones are removed so this runs once.
+pub fn migrate_cache_dir(cache_dir: &Path, runtime_dir: &Path, state_dir: &Path) -> Result<()> {
+    let _lock = lock_state_dir(state_dir)?;
+    let old_snapshots = cache_dir.join("snapshots.json");
+    let snapshots = state_dir.join("snapshots.json");
+    if old_snapshots.exists() {
+        if snapshots.exists() {
+            merge_snapshots(&old_snapshots, &snapshots)?;
+            fs::remove_file(&old_snapshots)?;
+        } else {
+            move_file(&old_snapshots, &snapshots)?;
+        }
+        crate::info!("Moved snapshots from {:?} to {:?}", cache_dir, state_dir);
+    }
+
+    let old_state = state_path(cache_dir);
+    if old_state.exists() {
+        // Windows ids are still valid when upgrading within a niri session, and pruned otherwise
+        let state = load_state(cache_dir)?;
+        if !preferences_path(state_dir).exists() {
+            save_preferences(&state, state_dir)?;
+        }
+        if !state_path(runtime_dir).exists() {
+            save_state(&state, runtime_dir)?;
+        }
+        if old_state.exists() {
+            fs::remove_file(&old_state)?;
+        }
+        crate::info!("Moved state from {:?} to {:?}", cache_dir, runtime_dir);
+    }
+
+    // Only goes away once nothing else is left in it
+    let _ = fs::remove_dir(cache_dir);
+    Ok(())
+}
+
+/// Adds the snapshots from `from` that `to` has no snapshot of the same name for.
+fn merge_snapshots(from: &Path, to: &Path) -> Result<()> {
+    let read = |path: &Path| -> Result<serde_json::Map<String, Value>> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).with_context(|| format!("Could not read {:?}", path))
+    };
+    let mut snapshots = read(to)?;
+    for (name, snapshot) in read(from)? {
+        snapshots.entry(name).or_insert(snapshot);
+    }
+    write_atomic(to, &serde_json::to_string_pretty(&snapshots)?)
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_err() {
+        // The cache and state dirs can be on different file systems
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Held around reading, changing and writing back files in the state dir, which unlike the runtime
+/// dir is shared by all niri sessions.
+pub fn lock_state_dir(state_dir: &Path) -> Result<LockFile> {
+    let mut lock_file = LockFile::open(&state_dir.join("state.lock"))?;
+    lock_file.lock()?;
+    Ok(lock_file)
+}
+
+/// Writes to a temporary file first, so a crash mid-write never leaves a truncated file behind.
+/// The temporary file is named after the process and write, so concurrent writers don't collide.
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let mut tmp_name = path
+        .file_name()
+        .context("Path has no file name")?
+        .to_owned();
+    tmp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
     Ok(())
 }
 
@@ -82,6 +375,7 @@ mod tests {
         };
 
         let original_state = AppState {
+            version: STATE_VERSION,
             windows: vec![w1, w2],
             ignored_windows: vec![100, 200],
             maximized_window_id: Some(100),
@@ -89,6 +383,16 @@ mod tests {
             is_hidden: true,
             is_flipped: true,
             scroll_offset: 42,
+            active_preset: Some("wide".into()),
+            tiled_origins: BTreeMap::from([(
+                100,
+                TiledOrigin {
+                    workspace_id: Some(2),
+                    column: 3,
+                    tile: 2,
+                    shared_column: true,
+                },
+            )]),
         };
 
         save_state(&original_state, temp_dir.path()).expect("Failed to save state");
@@ -101,6 +405,19 @@ mod tests {
         assert!(expected_path.exists());
     }
 
+    #[test]
+    fn test_session_id_is_stable_per_socket() {
+        assert_eq!(
+            session_id(b"/run/user/1000/niri.wayland-1.42.sock"),
+            session_id(b"/run/user/1000/niri.wayland-1.42.sock")
+        );
+        assert_ne!(
+            session_id(b"/run/user/1000/niri.wayland-1.42.sock"),
+            session_id(b"/run/user/1000/niri.wayland-2.43.sock")
+        );
+        assert_eq!(session_id(b""), "cbf29ce484222325");
+    }
+
     #[test]
     fn test_load_defaults_if_no_file() {
         let temp_dir = tempdir().unwrap();
@@ -127,5 +444,157 @@ mod tests {
         let state = load_state(temp_dir.path()).expect("Should recover from bad JSON");
 
         assert_eq!(state, AppState::default());
+        // The broken file is kept for inspection
+        assert!(!path.exists());
+        let bad = fs::read_to_string(temp_dir.path().join("state.json.bad")).unwrap();
+        assert_eq!(bad, "{ bad_json: ");
+    }
+
+    #[test]
+    fn test_new_session_starts_with_preferences() {
+        let runtime_dir = tempdir().unwrap();
+        let state_dir = tempdir().unwrap();
+        let state = AppState {
+            windows: vec![WindowState {
+                id: 7,
+                width: 300,
+                height: 200,
+                is_floating: true,
+                position: None,
+            }],
+            is_flipped: true,
+            active_preset: Some("wide".into()),
+            ..AppState::default()
+        };
+        save_preferences(&state, state_dir.path()).unwrap();
+
+        let restarted = load_session_state(runtime_dir.path(), state_dir.path()).unwrap();
+        assert_eq!(
+            restarted,
+            AppState {
+                is_flipped: true,
+                active_preset: Some("wide".into()),
+                ..AppState::default()
+            }
+        );
+
+        // Within a session the runtime state is what counts
+        save_state(&state, runtime_dir.path()).unwrap();
+        let reloaded = load_session_state(runtime_dir.path(), state_dir.path()).unwrap();
+        assert_eq!(reloaded, state);
+    }
+
+    #[test]
+    fn test_migrates_cache_dir_once() {
+        let cache_dir = tempdir().unwrap();
+        let runtime_dir = tempdir().unwrap();
+        let state_dir = tempdir().unwrap();
+        let old_state = AppState {
+            windows: vec![WindowState {
+                id: 7,
+                width: 300,
+                height: 200,
+                is_floating: true,
+                position: None,
+            }],
+            maximize_focus_mode: true,
+            active_preset: Some("glance".into()),
+            ..AppState::default()
+        };
+        save_state(&old_state, cache_dir.path()).unwrap();
+        fs::write(
+            cache_dir.path().join("snapshots.json"),
+            r#"{"old": 1, "both": 1}"#,
+        )
+        .unwrap();
+        fs::write(state_dir.path().join("snapshots.json"), r#"{"both": 2}"#).unwrap();
+
+        migrate_cache_dir(cache_dir.path(), runtime_dir.path(), state_dir.path()).unwrap();
+
+        assert!(!cache_dir.path().exists());
+        assert_eq!(load_state(runtime_dir.path()).unwrap(), old_state);
+        assert_eq!(
+            load_preferences(state_dir.path()).unwrap(),
+            Preferences::of(&old_state)
+        );
+        let snapshots: Value = serde_json::from_str(
+            &fs::read_to_string(state_dir.path().join("snapshots.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(snapshots, serde_json::json!({"old": 1, "both": 2}));
+    }
+
+    #[test]
+    fn test_migrates_unversioned_state() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(
+            state_path(temp_dir.path()),
+            r#"{
+                "windows": [
+                    { "id": 7, "width": 300, "height": 200, "is_floating": true, "position": null }
+                ],
+                "is_hidden": true
+            }"#,
+        )
+        .unwrap();
+
+        let state = load_state(temp_dir.path()).expect("Should migrate old state");
+
+        assert_eq!(state.version, STATE_VERSION);
+        assert_eq!(state.windows[0].id, 7);
+        assert!(state.tiled_origins.is_empty());
+        assert!(state.is_hidden);
+    }
+
+    #[test]
+    fn test_loads_state_from_newer_version() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(
+            state_path(temp_dir.path()),
+            r#"{ "version": 99, "windows": [], "scroll_offset": 5, "from_the_future": true }"#,
+        )
+        .unwrap();
+
+        let state = load_state(temp_dir.path()).expect("Should load newer state");
+
+        assert_eq!(state.version, STATE_VERSION);
+        assert_eq!(state.scroll_offset, 5);
+    }
+
+    #[test]
+    fn test_save_leaves_no_temporary_file() {
+        let temp_dir = tempdir().unwrap();
+
+        save_state(&AppState::default(), temp_dir.path()).expect("Failed to save state");
+        save_state(&AppState::default(), temp_dir.path()).expect("Failed to overwrite state");
+
+        let files: Vec<_> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec!["state.json"]);
+    }
+
+    #[test]
+    fn test_concurrent_writes_dont_collide() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("preferences.json");
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        write_atomic(path, &i.to_string()).expect("Write failed");
+                    }
+                });
+            }
+        });
+
+        let files: Vec<_> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec!["preferences.json"]);
     }
 }
@@ -1,11 +1,23 @@
 use crate::config::{Geometry, Margins, OverflowStrategy};
+use crate::layout::Screen;
 use crate::{Config, NiriClient};
 use anyhow::Result;
-use niri_ipc::{Action, Response, Window, WindowLayout, Workspace};
+use niri_ipc::{
+    Action, LayerSurface, LogicalOutput, Output, Response, Transform, Window, WindowLayout,
+    Workspace,
+};
 
+pub mod sim;
+
+pub use sim::NiriSim;
+
+/// Answers queries from fixed lists and only records actions, see [`NiriSim`] to apply them.
 #[derive(Default, Debug, Clone)]
 pub struct MockNiri {
     pub windows: Vec<Window>,
+    pub workspaces: Vec<Workspace>,
+    pub outputs: Vec<Output>,
+    pub layers: Vec<LayerSurface>,
     pub sent_actions: Vec<Action>,
 }
 
@@ -13,6 +25,9 @@ impl MockNiri {
     pub fn new(windows: Vec<Window>) -> Self {
         Self {
             windows,
+            workspaces: vec![mock_workspace(1, 0, "eDP-1", true)],
+            outputs: vec![mock_output("eDP-1", 1920, 1080)],
+            layers: vec![],
             sent_actions: vec![],
         }
     }
@@ -31,26 +46,39 @@ impl NiriClient for MockNiri {
             .ok_or_else(|| anyhow::anyhow!("No active window in mock"))
     }
 
+    fn get_layers(&mut self) -> Result<Vec<LayerSurface>> {
+        Ok(self.layers.clone())
+    }
+
     fn send_action(&mut self, action: Action) -> Result<Response> {
         self.sent_actions.push(action);
         Ok(Response::Handled)
     }
 
     fn get_active_workspace(&mut self) -> Result<Workspace> {
-        Ok(Workspace {
-            id: 1,
-            idx: 0,
-            name: Some("test".into()),
-            output: Some("eDP-1".into()),
-            is_urgent: false,
-            is_active: true,
-            is_focused: true,
-            active_window_id: None,
-        })
+        self.workspaces
+            .iter()
+            .find(|w| w.is_focused)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No active workspace in mock"))
+    }
+
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        Ok(self.workspaces.clone())
+    }
+
+    fn get_outputs(&mut self) -> Result<Vec<Output>> {
+        Ok(self.outputs.clone())
     }
 
-    fn get_screen_dimensions(&mut self) -> Result<(i32, i32)> {
-        Ok((1920, 1080))
+    fn get_screen(&mut self) -> Result<Screen> {
+        Ok(Screen {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            scale: 1.0,
+        })
     }
 }
 
@@ -81,11 +109,47 @@ pub fn mock_window(
     }
 }
 
+pub fn mock_workspace(id: u64, idx: u8, output: &str, is_focused: bool) -> Workspace {
+    Workspace {
+        id,
+        idx,
+        name: Some("test".into()),
+        output: Some(output.into()),
+        is_urgent: false,
+        is_active: is_focused,
+        is_focused,
+        active_window_id: None,
+    }
+}
+
+pub fn mock_output(name: &str, width: u32, height: u32) -> Output {
+    Output {
+        name: name.into(),
+        make: "Mock".into(),
+        model: "Display".into(),
+        serial: None,
+        physical_size: None,
+        modes: vec![],
+        current_mode: None,
+        is_custom_mode: false,
+        vrr_supported: false,
+        vrr_enabled: false,
+        logical: Some(LogicalOutput {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            scale: 1.0,
+            transform: Transform::Normal,
+        }),
+    }
+}
+
 pub fn mock_config() -> Config {
     Config {
         geometry: Geometry {
-            width: 300,
-            height: 200,
+            width: 300.into(),
+            height: 200.into(),
             gap: 10,
             overflow: OverflowStrategy::Scroll,
         },
@@ -94,6 +158,7 @@ pub fn mock_config() -> Config {
             right: 20,
             left: 10,
             bottom: 50,
+            auto_margins: false,
         },
         ..Default::default()
     }
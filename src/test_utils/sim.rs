@@ -0,0 +1,730 @@
+//! A small stateful stand-in for niri, for tests that care about where windows end up.
+//!
+//! Unlike [`MockNiri`](super::MockNiri), actions change the simulated windows and workspaces, so
+//! a test can run several commands in a row and check the final geometry. Every change is also
+//! queued as the event niri would send, to feed the event stream handling.
+
+use crate::NiriClient;
+use crate::layout::Screen;
+use crate::test_utils::{mock_output, mock_window, mock_workspace};
+use anyhow::{Context, Result, bail};
+use niri_ipc::{
+    Action, Event, LayerSurface, Output, PositionChange, Response, SizeChange, Timestamp, Window,
+    WindowLayout, Workspace, WorkspaceReferenceArg,
+};
+
+// Where niri puts windows that start floating without a position of their own
+const FLOATING_ORIGIN: (f64, f64) = (100.0, 100.0);
+
+#[derive(Debug, Clone)]
+pub struct NiriSim {
+    pub windows: Vec<Window>,
+    pub workspaces: Vec<Workspace>,
+    pub outputs: Vec<Output>,
+    pub layers: Vec<LayerSurface>,
+    pub sent_actions: Vec<Action>,
+    events: Vec<Event>,
+    next_window_id: u64,
+    // Stands in for the monotonic clock behind focus timestamps
+    focus_clock: u64,
+}
+
+impl Default for NiriSim {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NiriSim {
+    /// A single 1920x1080 output `eDP-1` showing workspace 1.
+    pub fn new() -> Self {
+        Self {
+            windows: vec![],
+            workspaces: vec![mock_workspace(1, 1, "eDP-1", true)],
+            outputs: vec![mock_output("eDP-1", 1920, 1080)],
+            layers: vec![],
+            sent_actions: vec![],
+            events: vec![],
+            next_window_id: 1,
+            focus_clock: 0,
+        }
+    }
+
+    /// Adds an output to the right of the existing ones, with a workspace of its own.
+    pub fn add_output(&mut self, name: &str, width: u32, height: u32) -> u64 {
+        let mut output = mock_output(name, width, height);
+        let x = self
+            .outputs
+            .iter()
+            .filter_map(|o| o.logical.as_ref())
+            .map(|l| l.x + l.width as i32)
+            .max()
+            .unwrap_or(0);
+        if let Some(logical) = &mut output.logical {
+            logical.x = x;
+        }
+        self.outputs.push(output);
+
+        let id = self.add_workspace(name);
+        self.workspace_mut(id).is_active = true;
+        self.queue_workspaces();
+        id
+    }
+
+    /// Adds an inactive workspace at the end of an output.
+    pub fn add_workspace(&mut self, output: &str) -> u64 {
+        let id = self.workspaces.iter().map(|w| w.id).max().unwrap_or(0) + 1;
+        let idx = self
+            .workspaces
+            .iter()
+            .filter(|w| w.output.as_deref() == Some(output))
+            .count() as u8
+            + 1;
+        let mut workspace = mock_workspace(id, idx, output, false);
+        workspace.name = None;
+        self.workspaces.push(workspace);
+        self.queue_workspaces();
+        id
+    }
+
+    /// Opens a focused window on a workspace, tiled or floating at niri's default spot.
+    pub fn open_window(&mut self, app_id: &str, workspace_id: u64, floating: bool) -> u64 {
+        let id = self.next_window_id;
+        self.next_window_id += 1;
+
+        let column = self
+            .windows
+            .iter()
+            .filter(|w| w.workspace_id == Some(workspace_id) && !w.is_floating)
+            .count()
+            + 1;
+        let mut window = mock_window(id, false, floating, workspace_id, None);
+        window.app_id = Some(app_id.into());
+        window.title = Some(app_id.into());
+        if floating {
+            window.layout.tile_pos_in_workspace_view = Some(FLOATING_ORIGIN);
+        } else {
+            window.layout.pos_in_scrolling_layout = Some((column, 1));
+        }
+        window.layout.tile_size = to_f64(window.layout.window_size);
+
+        self.windows.push(window.clone());
+        self.events.push(Event::WindowOpenedOrChanged { window });
+        self.focus_window(id);
+        id
+    }
+
+    pub fn window(&self, id: u64) -> &Window {
+        self.windows
+            .iter()
+            .find(|w| w.id == id)
+            .unwrap_or_else(|| panic!("no window {} in the simulation", id))
+    }
+
+    pub fn workspace(&self, id: u64) -> &Workspace {
+        self.workspaces
+            .iter()
+            .find(|w| w.id == id)
+            .unwrap_or_else(|| panic!("no workspace {} in the simulation", id))
+    }
+
+    /// The window's floating position, `None` while it is tiled.
+    pub fn position(&self, id: u64) -> Option<(f64, f64)> {
+        self.window(id).layout.tile_pos_in_workspace_view
+    }
+
+    pub fn size(&self, id: u64) -> (i32, i32) {
+        self.window(id).layout.window_size
+    }
+
+    /// Events for everything that changed since the last call, in the order niri would send them.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// The full window and workspace lists niri starts the event stream with.
+    pub fn initial_events(&self) -> Vec<Event> {
+        vec![
+            Event::WorkspacesChanged {
+                workspaces: self.workspaces.clone(),
+            },
+            Event::WindowsChanged {
+                windows: self.windows.clone(),
+            },
+        ]
+    }
+
+    /// Focuses a workspace the way a user switching to it would, without focusing a window on it.
+    pub fn focus_workspace(&mut self, id: u64) {
+        let output = self.workspace(id).output.clone();
+        for workspace in &mut self.workspaces {
+            if workspace.output == output {
+                workspace.is_active = workspace.id == id;
+            }
+            workspace.is_focused = workspace.id == id;
+        }
+        self.events
+            .push(Event::WorkspaceActivated { id, focused: true });
+
+        let active_window = self.workspace(id).active_window_id;
+        self.set_focused_window(active_window);
+    }
+
+    pub fn focus_window(&mut self, id: u64) {
+        let Some(workspace_id) = self.window(id).workspace_id else {
+            return;
+        };
+        if !self.workspace(workspace_id).is_focused {
+            self.focus_workspace(workspace_id);
+        }
+        self.workspace_mut(workspace_id).active_window_id = Some(id);
+        self.set_focused_window(Some(id));
+    }
+
+    fn set_focused_window(&mut self, id: Option<u64>) {
+        self.focus_clock += 1;
+        for window in &mut self.windows {
+            window.is_focused = Some(window.id) == id;
+            if window.is_focused {
+                window.focus_timestamp = Some(Timestamp {
+                    secs: self.focus_clock,
+                    nanos: 0,
+                });
+            }
+        }
+        self.events.push(Event::WindowFocusChanged { id });
+    }
+
+    fn apply(&mut self, action: &Action) -> Result<()> {
+        match action {
+            Action::MoveFloatingWindow { id, x, y } => {
+                let id = self.target(*id)?;
+                let (width, height) = self.working_area(id)?;
+                let window = self.window_mut(id);
+                // niri ignores this for tiled windows
+                if let Some((cur_x, cur_y)) = window.layout.tile_pos_in_workspace_view {
+                    window.layout.tile_pos_in_workspace_view = Some((
+                        change_position(cur_x, *x, width),
+                        change_position(cur_y, *y, height),
+                    ));
+                    self.queue_layout(id);
+                }
+            }
+            Action::SetWindowWidth { id, change } => {
+                let id = self.target(*id)?;
+                let (width, _) = self.working_area(id)?;
+                let size = &mut self.window_mut(id).layout.window_size;
+                size.0 = change_size(size.0, *change, width);
+                self.queue_layout(id);
+            }
+            Action::SetWindowHeight { id, change } => {
+                let id = self.target(*id)?;
+                let (_, height) = self.working_area(id)?;
+                let size = &mut self.window_mut(id).layout.window_size;
+                size.1 = change_size(size.1, *change, height);
+                self.queue_layout(id);
+            }
+            Action::ToggleWindowFloating { id } => {
+                let id = self.target(*id)?;
+                let workspace_id = self.window(id).workspace_id;
+                let window = self.window_mut(id);
+                window.is_floating = !window.is_floating;
+                if window.is_floating {
+                    window.layout.pos_in_scrolling_layout = None;
+                    window.layout.tile_pos_in_workspace_view = Some(FLOATING_ORIGIN);
+                    if let Some(workspace_id) = workspace_id {
+                        self.set_columns(workspace_id, self.columns(workspace_id));
+                    }
+                } else {
+                    window.layout.tile_pos_in_workspace_view = None;
+                    if let Some(workspace_id) = workspace_id {
+                        self.insert_column(workspace_id, id);
+                    }
+                }
+                self.queue_changed(id);
+            }
+            Action::MoveColumnToIndex { index } => {
+                let id = self.target(None)?;
+                let (workspace_id, (column, _)) = self.tile_of(id)?;
+                let mut columns = self.columns(workspace_id);
+                let moved = columns.remove(column - 1);
+                columns.insert(((*index).max(1) - 1).min(columns.len()), moved);
+                self.set_columns(workspace_id, columns);
+            }
+            Action::ConsumeOrExpelWindowLeft { id } => {
+                let id = self.target(*id)?;
+                let (workspace_id, (column, tile)) = self.tile_of(id)?;
+                let mut columns = self.columns(workspace_id);
+                if columns[column - 1].len() > 1 {
+                    // Expelled into a column of its own on the left
+                    columns[column - 1].remove(tile - 1);
+                    columns.insert(column - 1, vec![id]);
+                } else if column > 1 {
+                    // Consumed into the column on the left, at the bottom
+                    columns.remove(column - 1);
+                    columns[column - 2].push(id);
+                }
+                self.set_columns(workspace_id, columns);
+            }
+            Action::MoveWindowUp {} => {
+                let id = self.target(None)?;
+                let (workspace_id, (column, tile)) = self.tile_of(id)?;
+                let mut columns = self.columns(workspace_id);
+                if tile > 1 {
+                    columns[column - 1].swap(tile - 1, tile - 2);
+                }
+                self.set_columns(workspace_id, columns);
+            }
+            Action::MoveWindowToWorkspace {
+                window_id,
+                reference,
+                focus,
+            } => {
+                let id = self.target(*window_id)?;
+                let workspace_id = self.resolve_workspace(reference)?;
+                let from = self.window(id).workspace_id;
+                let pos = self.window_mut(id).layout.pos_in_scrolling_layout.take();
+                self.window_mut(id).workspace_id = Some(workspace_id);
+                if let (Some(from), Some(_)) = (from, pos) {
+                    self.set_columns(from, self.columns(from));
+                    self.insert_column(workspace_id, id);
+                }
+                for workspace in &mut self.workspaces {
+                    if workspace.active_window_id == Some(id) {
+                        workspace.active_window_id = None;
+                    }
+                }
+                self.queue_changed(id);
+                if *focus {
+                    self.focus_window(id);
+                } else if self.window(id).is_focused {
+                    self.set_focused_window(None);
+                }
+            }
+            Action::FocusWindow { id } => {
+                self.target(Some(*id))?;
+                self.focus_window(*id);
+            }
+            Action::CloseWindow { id } => {
+                let id = self.target(*id)?;
+                let was_focused = self.window(id).is_focused;
+                let workspace_id = self.window(id).workspace_id;
+                self.windows.retain(|w| w.id != id);
+                if let Some(workspace_id) = workspace_id {
+                    self.set_columns(workspace_id, self.columns(workspace_id));
+                }
+                for workspace in &mut self.workspaces {
+                    if workspace.active_window_id == Some(id) {
+                        workspace.active_window_id = None;
+                    }
+                }
+                self.events.push(Event::WindowClosed { id });
+                if was_focused {
+                    self.set_focused_window(None);
+                }
+            }
+            // Everything else only shows up in `sent_actions`
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The window an action applies to, the focused one if it names none.
+    fn target(&self, id: Option<u64>) -> Result<u64> {
+        match id {
+            Some(id) if self.windows.iter().any(|w| w.id == id) => Ok(id),
+            Some(id) => bail!("Window {} not found", id),
+            None => self
+                .windows
+                .iter()
+                .find(|w| w.is_focused)
+                .map(|w| w.id)
+                .context("No window focused"),
+        }
+    }
+
+    fn resolve_workspace(&self, reference: &WorkspaceReferenceArg) -> Result<u64> {
+        let focused_output = self
+            .workspaces
+            .iter()
+            .find(|w| w.is_focused)
+            .and_then(|w| w.output.clone());
+        self.workspaces
+            .iter()
+            .find(|w| match reference {
+                WorkspaceReferenceArg::Id(id) => w.id == *id,
+                WorkspaceReferenceArg::Index(idx) => w.idx == *idx && w.output == focused_output,
+                WorkspaceReferenceArg::Name(name) => w.name.as_deref() == Some(name.as_str()),
+            })
+            .map(|w| w.id)
+            .with_context(|| format!("Workspace {:?} not found", reference))
+    }
+
+    fn output_of(&self, workspace_id: Option<u64>) -> Result<&Output> {
+        let name = workspace_id
+            .and_then(|id| self.workspaces.iter().find(|w| w.id == id))
+            .and_then(|w| w.output.as_deref())
+            .context("Workspace has no output")?;
+        self.outputs
+            .iter()
+            .find(|o| o.name == name)
+            .with_context(|| format!("Output {} not found", name))
+    }
+
+    fn working_area(&self, window_id: u64) -> Result<(i32, i32)> {
+        let output = self.output_of(self.window(window_id).workspace_id)?;
+        let logical = output.logical.as_ref().context("Output is disabled")?;
+        Ok((logical.width as i32, logical.height as i32))
+    }
+
+    fn window_mut(&mut self, id: u64) -> &mut Window {
+        self.windows
+            .iter_mut()
+            .find(|w| w.id == id)
+            .expect("window ids are checked by target()")
+    }
+
+    fn workspace_mut(&mut self, id: u64) -> &mut Workspace {
+        self.workspaces
+            .iter_mut()
+            .find(|w| w.id == id)
+            .expect("workspace ids are checked before")
+    }
+
+    fn tile_of(&self, id: u64) -> Result<(u64, (usize, usize))> {
+        let window = self.window(id);
+        let workspace_id = window
+            .workspace_id
+            .context("Window is not on a workspace")?;
+        let pos = window
+            .layout
+            .pos_in_scrolling_layout
+            .with_context(|| format!("Window {} is not tiled", id))?;
+        Ok((workspace_id, pos))
+    }
+
+    /// The tiled windows of a workspace by column, each from top to bottom.
+    fn columns(&self, workspace_id: u64) -> Vec<Vec<u64>> {
+        let mut tiles: Vec<_> = self
+            .windows
+            .iter()
+            .filter(|w| w.workspace_id == Some(workspace_id))
+            .filter_map(|w| Some((w.layout.pos_in_scrolling_layout?, w.id)))
+            .collect();
+        tiles.sort();
+        let mut columns: Vec<Vec<u64>> = vec![];
+        let mut last_column = None;
+        for ((column, _), id) in tiles {
+            if last_column != Some(column) {
+                columns.push(vec![]);
+                last_column = Some(column);
+            }
+            columns.last_mut().expect("pushed above").push(id);
+        }
+        columns
+    }
+
+    /// Numbers the columns and tiles from 1 again, dropping empty columns like niri does.
+    fn set_columns(&mut self, workspace_id: u64, columns: Vec<Vec<u64>>) {
+        for (column, ids) in columns.into_iter().filter(|c| !c.is_empty()).enumerate() {
+            for (tile, id) in ids.into_iter().enumerate() {
+                let pos = Some((column + 1, tile + 1));
+                debug_assert_eq!(self.window(id).workspace_id, Some(workspace_id));
+                if self.window(id).layout.pos_in_scrolling_layout != pos {
+                    self.window_mut(id).layout.pos_in_scrolling_layout = pos;
+                    self.queue_layout(id);
+                }
+            }
+        }
+    }
+
+    /// Tiles a window as a new column right of the active one, which is the column focused last
+    /// even while a floating window has focus.
+    fn insert_column(&mut self, workspace_id: u64, id: u64) {
+        let active_column = self
+            .windows
+            .iter()
+            .filter(|w| w.id != id && w.workspace_id == Some(workspace_id))
+            .filter_map(|w| Some((w.focus_timestamp?.secs, w.layout.pos_in_scrolling_layout?.0)))
+            .max()
+            .map_or(0, |(_, column)| column);
+        let mut columns = self.columns(workspace_id);
+        columns.retain(|c| !c.contains(&id));
+        columns.insert(active_column.min(columns.len()), vec![id]);
+        self.set_columns(workspace_id, columns);
+    }
+
+    fn queue_layout(&mut self, id: u64) {
+        let window = self.window_mut(id);
+        window.layout.tile_size = to_f64(window.layout.window_size);
+        let layout: WindowLayout = window.layout.clone();
+        self.events.push(Event::WindowLayoutsChanged {
+            changes: vec![(id, layout)],
+        });
+    }
+
+    fn queue_changed(&mut self, id: u64) {
+        let window = self.window(id).clone();
+        self.events.push(Event::WindowOpenedOrChanged { window });
+    }
+
+    fn queue_workspaces(&mut self) {
+        self.events.push(Event::WorkspacesChanged {
+            workspaces: self.workspaces.clone(),
+        });
+    }
+}
+
+impl NiriClient for NiriSim {
+    fn get_windows(&mut self) -> Result<Vec<Window>> {
+        Ok(self.windows.clone())
+    }
+
+    fn get_active_window(&mut self) -> Result<Window> {
+        self.windows
+            .iter()
+            .find(|w| w.is_focused)
+            .cloned()
+            .context("No window focused")
+    }
+
+    fn get_active_workspace(&mut self) -> Result<Workspace> {
+        self.workspaces
+            .iter()
+            .find(|w| w.is_focused)
+            .cloned()
+            .context("No active workspace found")
+    }
+
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        Ok(self.workspaces.clone())
+    }
+
+    fn get_outputs(&mut self) -> Result<Vec<Output>> {
+        Ok(self.outputs.clone())
+    }
+
+    fn get_screen(&mut self) -> Result<Screen> {
+        let workspace = self.get_active_workspace()?;
+        let output = self.output_of(Some(workspace.id))?;
+        let logical = output.logical.as_ref().context("Output is disabled")?;
+        Ok(Screen {
+            x: logical.x,
+            y: logical.y,
+            width: logical.width as i32,
+            height: logical.height as i32,
+            scale: logical.scale,
+        })
+    }
+
+    fn get_layers(&mut self) -> Result<Vec<LayerSurface>> {
+        Ok(self.layers.clone())
+    }
+
+    fn send_action(&mut self, action: Action) -> Result<Response> {
+        let result = self.apply(&action);
+        self.sent_actions.push(action);
+        result.map(|()| Response::Handled)
+    }
+}
+
+fn change_size(current: i32, change: SizeChange, available: i32) -> i32 {
+    // Proportions are in percent, like `50%` on niri's command line
+    let size = match change {
+        SizeChange::SetFixed(px) => px,
+        SizeChange::SetProportion(p) => (f64::from(available) * p / 100.0).round() as i32,
+        SizeChange::AdjustFixed(px) => current + px,
+        SizeChange::AdjustProportion(p) => {
+            current + (f64::from(available) * p / 100.0).round() as i32
+        }
+    };
+    size.max(1)
+}
+
+fn change_position(current: f64, change: PositionChange, available: i32) -> f64 {
+    match change {
+        PositionChange::SetFixed(px) => px,
+        PositionChange::SetProportion(p) => f64::from(available) * p / 100.0,
+        PositionChange::AdjustFixed(px) => current + px,
+        PositionChange::AdjustProportion(p) => current + f64::from(available) * p / 100.0,
+    }
+}
+
+fn to_f64((w, h): (i32, i32)) -> (f64, f64) {
+    (f64::from(w), f64::from(h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{EventBurst, process_burst, toggle_visibility, toggle_window};
+    use crate::niri::EventStreamClient;
+    use crate::state::AppState;
+    use crate::test_utils::mock_config;
+    use crate::{Ctx, commands};
+    use tempfile::{TempDir, tempdir};
+
+    fn setup(sim: NiriSim) -> (TempDir, Ctx<NiriSim>) {
+        let temp_dir = tempdir().unwrap();
+        let ctx = Ctx {
+            state: AppState::default(),
+            config: mock_config(),
+            socket: sim,
+            runtime_dir: temp_dir.path().to_path_buf(),
+            state_dir: temp_dir.path().to_path_buf(),
+        };
+        (temp_dir, ctx)
+    }
+
+    #[test]
+    fn test_toggle_window_floats_resizes_and_places_it() {
+        let mut sim = NiriSim::new();
+        let id = sim.open_window("telegram", 1, false);
+        let (_temp_dir, mut ctx) = setup(sim);
+
+        toggle_window(&mut ctx).expect("toggle_window failed");
+
+        let window = ctx.socket.window(id);
+        assert!(window.is_floating);
+        assert_eq!(ctx.socket.size(id), (300, 200));
+        // Right edge with mock_config's 20px margin, bottom with its 50px margin
+        assert_eq!(
+            ctx.socket.position(id),
+            Some((1920.0 - 300.0 - 20.0, 1080.0 - 200.0 - 50.0))
+        );
+    }
+
+    #[test]
+    fn test_sidebar_windows_never_overlap_after_several_commands() {
+        let mut sim = NiriSim::new();
+        let ids: Vec<_> = (0..3)
+            .map(|i| sim.open_window(&format!("app{}", i), 1, false))
+            .collect();
+        let (_temp_dir, mut ctx) = setup(sim);
+
+        for id in &ids {
+            ctx.socket.focus_window(*id);
+            toggle_window(&mut ctx).expect("toggle_window failed");
+        }
+        commands::toggle_flip(&mut ctx).expect("flip failed");
+
+        let mut tops: Vec<_> = ids
+            .iter()
+            .map(|id| ctx.socket.position(*id).unwrap().1)
+            .collect();
+        tops.sort_by(f64::total_cmp);
+        for pair in tops.windows(2) {
+            assert!(pair[1] - pair[0] >= 200.0 + 10.0, "overlap in {:?}", tops);
+        }
+
+        // Hiding tucks every window away to the right, with only the peek left on screen
+        toggle_visibility(&mut ctx).expect("hide failed");
+        for id in &ids {
+            assert!(ctx.socket.position(*id).unwrap().0 > 1920.0 - 300.0);
+        }
+    }
+
+    #[test]
+    fn test_closing_a_window_through_the_event_stream_closes_the_gap() {
+        let mut sim = NiriSim::new();
+        let first = sim.open_window("first", 1, false);
+        let second = sim.open_window("second", 1, false);
+        let (_temp_dir, ctx) = setup(sim);
+        let Ctx {
+            state,
+            config,
+            socket,
+            runtime_dir,
+            state_dir,
+        } = ctx;
+        let initial = socket.initial_events();
+        let mut ctx = Ctx {
+            state,
+            config,
+            socket: EventStreamClient::new(socket),
+            runtime_dir,
+            state_dir,
+        };
+        for event in initial {
+            ctx.socket.apply(event);
+        }
+
+        for id in [first, second] {
+            ctx.socket.inner_mut().focus_window(id);
+            deliver_events(&mut ctx);
+            toggle_window(&mut ctx).expect("toggle_window failed");
+            deliver_events(&mut ctx);
+        }
+        let second_y = ctx.socket.inner_mut().position(second).unwrap().1;
+        ctx.socket
+            .inner_mut()
+            .send_action(Action::CloseWindow { id: Some(first) })
+            .unwrap();
+
+        let burst = deliver_events(&mut ctx);
+        process_burst(&mut ctx, &burst).expect("process_burst failed");
+
+        // The sidebar stacks up from the bottom margin, so the second window drops into the gap
+        let sim = ctx.socket.inner_mut();
+        assert!(sim.position(second).unwrap().1 > second_y);
+        assert_eq!(sim.position(second), Some((1600.0, 830.0)));
+    }
+
+    /// Hands what happened in the simulation to the event stream model, like the daemon does.
+    fn deliver_events(ctx: &mut Ctx<EventStreamClient<NiriSim>>) -> EventBurst {
+        let mut burst = EventBurst::default();
+        for event in ctx.socket.inner_mut().take_events() {
+            burst.push(&event);
+            ctx.socket.apply(event);
+        }
+        ctx.socket.mark_synced();
+        burst
+    }
+
+    #[test]
+    fn test_toggle_window_twice_restores_a_shared_column() {
+        let mut sim = NiriSim::new();
+        let ids: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|app| sim.open_window(app, 1, false))
+            .collect();
+        let (a, b, c, d) = (ids[0], ids[1], ids[2], ids[3]);
+        sim.send_action(Action::ConsumeOrExpelWindowLeft { id: Some(c) })
+            .unwrap();
+        let (_temp_dir, mut ctx) = setup(sim);
+        ctx.socket.focus_window(c);
+        toggle_window(&mut ctx).expect("toggle_window failed");
+
+        // niri tiles the window again right of the column focused last, left of where it was
+        ctx.socket.focus_window(a);
+        ctx.socket.focus_window(c);
+        toggle_window(&mut ctx).expect("toggle_window failed");
+
+        let pos = |id| ctx.socket.window(id).layout.pos_in_scrolling_layout;
+        assert_eq!(
+            [pos(a), pos(b), pos(c), pos(d)],
+            [Some((1, 1)), Some((2, 1)), Some((2, 2)), Some((3, 1))]
+        );
+        assert!(ctx.socket.window(c).is_focused);
+    }
+
+    #[test]
+    fn test_move_to_another_output_follows_its_workspace() {
+        let mut sim = NiriSim::new();
+        let id = sim.open_window("chat", 1, false);
+        let external = sim.add_output("HDMI-A-1", 2560, 1440);
+        let (_temp_dir, mut ctx) = setup(sim);
+
+        toggle_window(&mut ctx).expect("toggle_window failed");
+        commands::move_to(&mut ctx, commands::MoveTarget::Output("HDMI-A-1".into()))
+            .expect("move_to failed");
+
+        assert_eq!(ctx.socket.window(id).workspace_id, Some(external));
+        assert_eq!(ctx.socket.workspace(external).idx, 1);
+        // Laid out for the larger output right away, without waiting for the daemon
+        assert_eq!(
+            ctx.socket.position(id),
+            Some((2560.0 - 300.0 - 20.0, 1440.0 - 200.0 - 50.0))
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use niri_ipc::Window;
 
-use crate::config::WindowRule;
+use crate::config::{Dimension, WindowRule};
 
 fn matches_window(app_id: &Option<String>, title: &Option<String>, rule: &WindowRule) -> bool {
     let app_ok = match (&rule.app_id, app_id) {
@@ -18,21 +18,29 @@ fn matches_window(app_id: &Option<String>, title: &Option<String>, rule: &Window
     title_ok && app_ok
 }
 
+pub fn matching_rule_index(rules: &[WindowRule], window: &Window) -> Option<usize> {
+    rules
+        .iter()
+        .position(|rule| matches_window(&window.app_id, &window.title, rule))
+}
+
+/// Resolves the sidebar size of a window, relative sizes are taken of the `screen` size.
 pub fn resolve_window_size(
     rules: &[WindowRule],
     window: &Window,
-    default_w: i32,
-    default_h: i32,
+    default_w: Dimension,
+    default_h: Dimension,
+    screen: (i32, i32),
 ) -> (i32, i32) {
+    let (mut width, mut height) = (default_w, default_h);
     for rule in rules {
         if matches_window(&window.app_id, &window.title, rule) {
-            return (
-                rule.width.unwrap_or(default_w),
-                rule.height.unwrap_or(default_h),
-            );
+            width = rule.width.unwrap_or(default_w);
+            height = rule.height.unwrap_or(default_h);
+            break;
         }
     }
-    (default_w, default_h)
+    (width.resolve(screen.0), height.resolve(screen.1))
 }
 
 pub fn resolve_rule_peek(rules: &[WindowRule], window: &Window, default_peek: i32) -> i32 {
@@ -66,17 +74,29 @@ pub fn resolve_auto_add(rules: &[WindowRule], window: &Window) -> bool {
     false
 }
 
+pub fn resolve_max_instances(rules: &[WindowRule], window: &Window) -> Option<usize> {
+    for rule in rules {
+        if matches_window(&window.app_id, &window.title, rule) {
+            return rule.max_instances;
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Size;
     use crate::test_utils::mock_window;
     use regex::Regex;
 
+    const SCREEN: (i32, i32) = (1920, 1080);
+
     #[test]
     fn test_resolve_window_size_defaults() {
         let rules = vec![];
         let window = mock_window(1, false, false, 1, Some((1.0, 2.0)));
-        let (w, h) = resolve_window_size(&rules, &window, 100, 200);
+        let (w, h) = resolve_window_size(&rules, &window, 100.into(), 200.into(), SCREEN);
         assert_eq!(w, 100);
         assert_eq!(h, 200);
     }
@@ -85,12 +105,12 @@ mod tests {
     fn test_resolve_window_size_match_app_id() {
         let rules = vec![WindowRule {
             app_id: Some(Regex::new("test").unwrap()),
-            width: Some(500),
-            height: Some(600),
+            width: Some(500.into()),
+            height: Some(600.into()),
             ..Default::default()
         }];
         let window = mock_window(1, false, false, 1, Some((1.0, 2.0))); // mock_window has app_id "test"
-        let (w, h) = resolve_window_size(&rules, &window, 100, 200);
+        let (w, h) = resolve_window_size(&rules, &window, 100.into(), 200.into(), SCREEN);
         assert_eq!(w, 500);
         assert_eq!(h, 600);
     }
@@ -99,12 +119,12 @@ mod tests {
     fn test_resolve_window_size_match_title() {
         let rules = vec![WindowRule {
             title: Some(Regex::new("Test Window").unwrap()),
-            width: Some(800),
-            height: Some(900),
+            width: Some(800.into()),
+            height: Some(900.into()),
             ..Default::default()
         }];
         let window = mock_window(1, false, false, 1, Some((1.0, 2.0))); // mock_window has title "Test Window"
-        let (w, h) = resolve_window_size(&rules, &window, 100, 200);
+        let (w, h) = resolve_window_size(&rules, &window, 100.into(), 200.into(), SCREEN);
         assert_eq!(w, 800);
         assert_eq!(h, 900);
     }
@@ -113,16 +133,35 @@ mod tests {
     fn test_resolve_window_size_no_match() {
         let rules = vec![WindowRule {
             app_id: Some(Regex::new("nomatch").unwrap()),
-            width: Some(500),
-            height: Some(600),
+            width: Some(500.into()),
+            height: Some(600.into()),
             ..Default::default()
         }];
         let window = mock_window(1, false, false, 1, Some((1.0, 2.0)));
-        let (w, h) = resolve_window_size(&rules, &window, 100, 200);
+        let (w, h) = resolve_window_size(&rules, &window, 100.into(), 200.into(), SCREEN);
         assert_eq!(w, 100);
         assert_eq!(h, 200);
     }
 
+    #[test]
+    fn test_resolve_window_size_relative_to_screen() {
+        let rules = vec![WindowRule {
+            app_id: Some(Regex::new("test").unwrap()),
+            height: Some(Size::Percent(50.0).into()),
+            ..Default::default()
+        }];
+        let window = mock_window(1, false, false, 1, Some((1.0, 2.0)));
+        let default_w = Size::Percent(25.0).into();
+
+        let (w, h) = resolve_window_size(&rules, &window, default_w, 200.into(), (3840, 2160));
+        assert_eq!(w, 960);
+        assert_eq!(h, 1080);
+
+        let (w, h) = resolve_window_size(&rules, &window, default_w, 200.into(), SCREEN);
+        assert_eq!(w, 480);
+        assert_eq!(h, 540);
+    }
+
     #[test]
     fn test_resolve_rule_peek_match() {
         let rules = vec![WindowRule {
@@ -194,4 +233,43 @@ mod tests {
         let auto_add = resolve_auto_add(&rules, &window);
         assert!(!auto_add);
     }
+
+    #[test]
+    fn test_resolve_max_instances_match() {
+        let rules = vec![WindowRule {
+            app_id: Some(Regex::new("test").unwrap()),
+            max_instances: Some(2),
+            ..Default::default()
+        }];
+        let window = mock_window(1, false, false, 1, Some((1.0, 2.0)));
+        assert_eq!(resolve_max_instances(&rules, &window), Some(2));
+    }
+
+    #[test]
+    fn test_resolve_max_instances_default_unlimited() {
+        let rules = vec![WindowRule {
+            app_id: Some(Regex::new("nomatch").unwrap()),
+            max_instances: Some(2),
+            ..Default::default()
+        }];
+        let window = mock_window(1, false, false, 1, Some((1.0, 2.0)));
+        assert_eq!(resolve_max_instances(&rules, &window), None);
+    }
+
+    #[test]
+    fn test_matching_rule_index_uses_first_match() {
+        let rules = vec![
+            WindowRule {
+                app_id: Some(Regex::new("nomatch").unwrap()),
+                ..Default::default()
+            },
+            WindowRule {
+                app_id: Some(Regex::new("test").unwrap()),
+                ..Default::default()
+            },
+            WindowRule::default(),
+        ];
+        let window = mock_window(1, false, false, 1, Some((1.0, 2.0)));
+        assert_eq!(matching_rule_index(&rules, &window), Some(1));
+    }
 }
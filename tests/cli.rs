@@ -0,0 +1,269 @@
+//! End-to-end tests running the `niri-sidebar` binary against a fake niri.
+
+mod fake_niri;
+
+use fake_niri::{FakeNiri, Model};
+use niri_ipc::{
+    Action, Event, LogicalOutput, Output, Request, Transform, Window, WindowLayout, Workspace,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output as ProcessOutput, Stdio};
+use tempfile::{TempDir, tempdir};
+
+struct Env {
+    dir: TempDir,
+    niri: FakeNiri,
+}
+
+impl Env {
+    fn new(windows: Vec<Window>) -> Self {
+        let dir = tempdir().unwrap();
+        let niri = FakeNiri::start(
+            dir.path(),
+            Model {
+                windows,
+                workspaces: vec![workspace(1, 1, true), workspace(2, 2, false)],
+                outputs: vec![output("eDP-1", 1920, 1080)],
+                ..Default::default()
+            },
+        );
+        Self { dir, niri }
+    }
+
+    fn config_path(&self) -> PathBuf {
+        self.dir.path().join("config.toml")
+    }
+
+    fn state_dir(&self) -> PathBuf {
+        self.dir.path().join("state")
+    }
+
+    fn runtime_dir(&self) -> PathBuf {
+        self.dir.path().join("runtime")
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_niri-sidebar"));
+        command
+            .args(args)
+            .arg("--config")
+            .arg(self.config_path())
+            .arg("--state-dir")
+            .arg(self.state_dir())
+            .env("NIRI_SOCKET", self.niri.socket_path())
+            .env("NIRI_SIDEBAR_RUNTIME_DIR", self.runtime_dir())
+            .env_remove("NIRI_SIDEBAR_CONFIG")
+            .env_remove("NIRI_SIDEBAR_STATE_DIR")
+            .env_remove("NIRI_SIDEBAR_LOG")
+            .env_remove("JOURNAL_STREAM");
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> ProcessOutput {
+        self.command(args)
+            .output()
+            .expect("could not run niri-sidebar")
+    }
+
+    fn spawn(&self, args: &[&str]) -> KillOnDrop {
+        let child = self
+            .command(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("could not run niri-sidebar");
+        KillOnDrop(child)
+    }
+}
+
+/// Stops the daemon even if the test fails halfway.
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn window(id: u64, app_id: &str, is_focused: bool, is_floating: bool) -> Window {
+    Window {
+        id,
+        title: Some(app_id.into()),
+        app_id: Some(app_id.into()),
+        pid: None,
+        workspace_id: Some(1),
+        is_focused,
+        is_floating,
+        is_urgent: false,
+        layout: WindowLayout {
+            pos_in_scrolling_layout: (!is_floating).then_some((1, 1)),
+            tile_size: (1000.0, 800.0),
+            window_size: (1000, 800),
+            tile_pos_in_workspace_view: is_floating.then_some((100.0, 100.0)),
+            window_offset_in_tile: (0.0, 0.0),
+        },
+        focus_timestamp: None,
+    }
+}
+
+fn workspace(id: u64, idx: u8, is_focused: bool) -> Workspace {
+    Workspace {
+        id,
+        idx,
+        name: None,
+        output: Some("eDP-1".into()),
+        is_urgent: false,
+        is_active: is_focused,
+        is_focused,
+        active_window_id: None,
+    }
+}
+
+fn output(name: &str, width: u32, height: u32) -> Output {
+    Output {
+        name: name.into(),
+        make: "Fake".into(),
+        model: "Display".into(),
+        serial: None,
+        physical_size: None,
+        modes: vec![],
+        current_mode: None,
+        is_custom_mode: false,
+        vrr_supported: false,
+        vrr_enabled: false,
+        logical: Some(LogicalOutput {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            scale: 1.0,
+            transform: Transform::Normal,
+        }),
+    }
+}
+
+fn read_state(runtime_dir: &Path) -> serde_json::Value {
+    let content = fs::read_to_string(runtime_dir.join("state.json")).expect("no state written");
+    serde_json::from_str(&content).unwrap()
+}
+
+#[test]
+fn test_toggle_window_floats_and_resizes_the_focused_window() {
+    let env = Env::new(vec![window(7, "telegram", true, false)]);
+
+    let output = env.run(&["toggle-window"]);
+
+    assert!(output.status.success(), "{:?}", output);
+    let actions = env.niri.actions();
+    assert!(matches!(
+        actions[0],
+        Action::ToggleWindowFloating { id: Some(7) }
+    ));
+    assert!(
+        actions
+            .iter()
+            .any(|a| matches!(a, Action::SetWindowWidth { id: Some(7), .. }))
+    );
+    assert!(
+        actions
+            .iter()
+            .any(|a| matches!(a, Action::SetWindowHeight { id: Some(7), .. }))
+    );
+    assert_eq!(read_state(&env.runtime_dir())["windows"][0]["id"], 7);
+}
+
+#[test]
+fn test_layout_only_queries_niri() {
+    let env = Env::new(vec![window(7, "telegram", true, true)]);
+    fs::create_dir_all(env.runtime_dir()).unwrap();
+    fs::write(
+        env.runtime_dir().join("state.json"),
+        r#"{"version": 1, "windows": [{"id": 7, "width": 1000, "height": 800, "is_floating": false}]}"#,
+    )
+    .unwrap();
+
+    let output = env.run(&["layout", "--format", "json"]);
+
+    assert!(output.status.success(), "{:?}", output);
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(plan["workspace_id"], 1);
+    assert!(env.niri.actions().is_empty());
+}
+
+#[test]
+fn test_errors_are_logged_to_the_state_dir() {
+    let env = Env::new(vec![]);
+
+    let output = env.run(&["move-to", "--output", "DP-9"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("No active workspace on output DP-9"),
+        "{}",
+        stderr
+    );
+    let log = fs::read_to_string(env.state_dir().join("niri-sidebar.log")).unwrap();
+    assert!(log.contains("ERROR"), "{}", log);
+    assert!(
+        log.contains("No active workspace on output DP-9"),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn test_verbose_logs_every_action() {
+    let env = Env::new(vec![window(7, "telegram", true, false)]);
+
+    let output = env.run(&["-v", "toggle-window"]);
+
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("[DEBUG] Sending ToggleWindowFloating"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_listen_adopts_new_windows_matching_an_auto_add_rule() {
+    let env = Env::new(vec![window(1, "firefox", true, false)]);
+    fs::write(
+        env.config_path(),
+        format!(
+            "{}\n[[window_rule]]\napp_id = \"^telegram$\"\nauto_add = true\n",
+            include_str!("../default_config.toml")
+        ),
+    )
+    .unwrap();
+    let _daemon = env.spawn(&["listen"]);
+    env.niri.wait_for("the event stream", |requests| {
+        requests.iter().any(|r| matches!(r, Request::EventStream))
+    });
+
+    let telegram = window(2, "telegram", false, false);
+    env.niri
+        .update(|model| model.windows.push(telegram.clone()));
+    env.niri
+        .emit(Event::WindowOpenedOrChanged { window: telegram });
+
+    env.niri.wait_for("telegram to be floated", |requests| {
+        requests.iter().any(|r| {
+            matches!(
+                r,
+                Request::Action(Action::ToggleWindowFloating { id: Some(2) })
+            )
+        })
+    });
+    // The firefox window was open before and doesn't match the rule
+    assert!(
+        !env.niri
+            .actions()
+            .iter()
+            .any(|a| matches!(a, Action::ToggleWindowFloating { id: Some(1) }))
+    );
+}
@@ -0,0 +1,160 @@
+//! A fake niri speaking its JSON IPC on a Unix socket, so the real binary can be tested without a
+//! compositor. It answers queries from a model the test sets up, records every request and
+//! replies to actions without carrying them out.
+
+use niri_ipc::{Event, LayerSurface, Output, Reply, Request, Response, Window, Workspace};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct Model {
+    pub windows: Vec<Window>,
+    pub workspaces: Vec<Workspace>,
+    pub outputs: Vec<Output>,
+    pub layers: Vec<LayerSurface>,
+    pub requests: Vec<Request>,
+}
+
+impl Model {
+    fn reply(&self, request: &Request) -> Reply {
+        match request {
+            Request::Windows => Ok(Response::Windows(self.windows.clone())),
+            Request::FocusedWindow => Ok(Response::FocusedWindow(
+                self.windows.iter().find(|w| w.is_focused).cloned(),
+            )),
+            Request::Workspaces => Ok(Response::Workspaces(self.workspaces.clone())),
+            Request::Outputs => Ok(Response::Outputs(
+                self.outputs
+                    .iter()
+                    .map(|o| (o.name.clone(), o.clone()))
+                    .collect::<HashMap<_, _>>(),
+            )),
+            Request::Layers => Ok(Response::Layers(self.layers.clone())),
+            Request::Action(_) | Request::EventStream => Ok(Response::Handled),
+            other => Err(format!("the fake niri doesn't handle {:?}", other)),
+        }
+    }
+}
+
+pub struct FakeNiri {
+    socket_path: PathBuf,
+    model: Arc<Mutex<Model>>,
+    event_streams: Arc<Mutex<Vec<UnixStream>>>,
+}
+
+impl FakeNiri {
+    /// Listens on `niri.sock` in `dir` until the test ends.
+    pub fn start(dir: &Path, model: Model) -> Self {
+        let socket_path = dir.join("niri.sock");
+        let listener = UnixListener::bind(&socket_path).expect("could not bind the fake socket");
+        let model = Arc::new(Mutex::new(model));
+        let event_streams = Arc::new(Mutex::new(vec![]));
+
+        let (shared_model, shared_streams) = (model.clone(), event_streams.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let model = shared_model.clone();
+                let event_streams = shared_streams.clone();
+                thread::spawn(move || serve(stream, model, event_streams));
+            }
+        });
+
+        Self {
+            socket_path,
+            model,
+            event_streams,
+        }
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    pub fn update(&self, change: impl FnOnce(&mut Model)) {
+        change(&mut self.model.lock().unwrap());
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.model.lock().unwrap().requests.clone()
+    }
+
+    pub fn actions(&self) -> Vec<niri_ipc::Action> {
+        self.requests()
+            .into_iter()
+            .filter_map(|r| match r {
+                Request::Action(action) => Some(action),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Sends an event to every client subscribed to the event stream.
+    pub fn emit(&self, event: Event) {
+        let line = to_line(&event);
+        self.event_streams
+            .lock()
+            .unwrap()
+            .retain_mut(|stream| stream.write_all(line.as_bytes()).is_ok());
+    }
+
+    /// Polls until `condition` holds for the requests received so far, panics after a timeout.
+    pub fn wait_for(&self, what: &str, condition: impl Fn(&[Request]) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition(&self.requests()) {
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for {}, got {:?}",
+                what,
+                self.requests()
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+fn serve(stream: UnixStream, model: Arc<Mutex<Model>>, event_streams: Arc<Mutex<Vec<UnixStream>>>) {
+    let mut writer = stream.try_clone().expect("could not clone the stream");
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    while matches!(reader.read_line(&mut line), Ok(n) if n > 0) {
+        let request: Request = serde_json::from_str(&line).expect("invalid request");
+        line.clear();
+
+        let mut model = model.lock().unwrap();
+        let reply = model.reply(&request);
+        let is_event_stream = matches!(request, Request::EventStream);
+        model.requests.push(request);
+        if writer.write_all(to_line(&reply).as_bytes()).is_err() {
+            return;
+        }
+
+        // Like niri, the stream starts with the full workspace and window lists
+        if is_event_stream {
+            let initial = [
+                Event::WorkspacesChanged {
+                    workspaces: model.workspaces.clone(),
+                },
+                Event::WindowsChanged {
+                    windows: model.windows.clone(),
+                },
+            ];
+            for event in &initial {
+                let _ = writer.write_all(to_line(event).as_bytes());
+            }
+            event_streams.lock().unwrap().push(writer);
+            return;
+        }
+    }
+}
+
+fn to_line(value: &impl serde::Serialize) -> String {
+    let mut line = serde_json::to_string(value).unwrap();
+    line.push('\n');
+    line
+}